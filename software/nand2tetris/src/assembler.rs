@@ -4,6 +4,7 @@ use std::io::BufRead;
 use symbol_table::{Address, SymbolTable};
 
 #[derive(Debug, PartialEq)]
+#[allow(clippy::enum_variant_names)]
 enum Instruction {
     AInstruction(AInstructionArg),
     CInstruction {
@@ -200,8 +201,7 @@ fn parse_instruction_lines(instruction_lines: Vec<String>) -> Result<Vec<Instruc
 }
 
 fn parse_instruction_line(line: &str) -> Result<Instruction, String> {
-    if line.starts_with('@') {
-        let arg = &line[1..];
+    if let Some(arg) = line.strip_prefix('@') {
         if let Ok(value) = arg.parse::<u16>() {
             Ok(Instruction::AInstruction(AInstructionArg::Value(value)))
         } else {
@@ -246,14 +246,12 @@ fn read_instruction_lines(file_path: &str) -> Vec<String> {
     };
     let reader = std::io::BufReader::new(file);
 
-    for line in reader.lines() {
-        if let Ok(line) = line {
-            let line = line.trim().to_string();
-            if line.is_empty() || line.starts_with("//") {
-                continue;
-            }
-            lines.push(line);
+    for line in reader.lines().map_while(Result::ok) {
+        let line = line.trim().to_string();
+        if line.is_empty() || line.starts_with("//") {
+            continue;
         }
+        lines.push(line);
     }
 
     lines
@@ -2,18 +2,16 @@ use clap::Parser;
 use nand2tetris::jack::{self, AnalyzerCli};
 
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+fn main() {
     let config = AnalyzerCli::parse();
     let jack_files = jack::get_jack_files(&config.source);
 
-    let output_dir = match &config.output_dir {
-        Some(dir) => Some(dir.as_str()),
-        None => None,
-    };
+    let output_dir = config.output_dir.as_deref();
 
     for file in jack_files {
-        jack::analyze_file(&file, output_dir)?;
+        if let Err(e) = jack::analyze_file(&file, output_dir) {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
     }
-
-    Ok(())
 }
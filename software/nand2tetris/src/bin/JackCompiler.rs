@@ -1,18 +1,19 @@
 use clap::Parser;
 use nand2tetris::jack::{self, CompilerCli};
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+fn main() {
     let config = CompilerCli::parse();
     let jack_files = jack::get_jack_files(&config.source);
 
-    let output_dir = match &config.output_dir {
-        Some(dir) => Some(dir.as_str()),
-        None => None,
+    let output_dir = config.output_dir.as_deref();
+    let options = jack::CompileOptions {
+        language: config.language,
+        precedence: config.precedence,
     };
 
-    for file in jack_files {
-        jack::compile_file(&file, output_dir)?;
+    // Diagnostics are rendered already, so print them as they are
+    if let Err(e) = jack::compile_files(&jack_files, output_dir, &options) {
+        eprintln!("{e}");
+        std::process::exit(1);
     }
-
-    Ok(())
 }
@@ -0,0 +1,10 @@
+use clap::Parser;
+use nand2tetris::vmtrans::{self, BytecodeCli};
+
+fn main() {
+    let config = BytecodeCli::parse();
+    if let Err(e) = vmtrans::convert_bytecode(&config) {
+        eprintln!("Error while converting: {}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,10 @@
+use clap::Parser;
+use nand2tetris::vmtrans::{self, CallGraphCli};
+
+fn main() {
+    let config = CallGraphCli::parse();
+    if let Err(e) = vmtrans::write_call_graph(&config) {
+        eprintln!("Error while building the call graph: {}", e);
+        std::process::exit(1);
+    }
+}
@@ -1,9 +1,9 @@
 use clap::Parser;
-use nand2tetris::vmtrans::{Cli, code_writer};
+use nand2tetris::vmtrans::{self, Cli};
 
 fn main() {
     let config = Cli::parse();
-    match code_writer::write_asm_code(&config) {
+    match vmtrans::write_code(&config) {
         Ok(_) => println!("Translation completed successfully."),
         Err(e) => {
             eprintln!("Error during translation: {}", e);
@@ -6,25 +6,31 @@ mod parser;
 mod ast;
 pub mod parse_tree_converter;
 mod compiler;
+mod diagnostic;
+mod checker;
+mod os_api;
 mod cli;
 mod symbol_table;
 
 use std::path::Path;
-pub use lexer::Lexer;
-pub use parser::Parser;
+pub use lexer::{LanguageLevel, Lexer};
+pub use parser::{Parser, Precedence};
+pub use diagnostic::{Diagnostic, Severity, Span};
 pub use cli::{AnalyzerCli, CompilerCli, get_jack_files};
 
 use crate::grammarous::string_char_stream::StringCharStream;
+use crate::jack::checker::ProgramSignatures;
 use crate::jack::parse_tree_printer::{ParseTreePrinter, StringOutput};
 
 pub fn analyze_file(file_path: &str, output_dir: Option<&str>) -> Result<(), anyhow::Error> {
-    let mut stream = StringCharStream::new_from_file(file_path)?;
+    let source = std::fs::read_to_string(file_path)?;
+    let mut stream = StringCharStream::new(&source);
     let mut lexer = Lexer::new(&mut stream);
     let mut parser = Parser::new(&mut lexer);
 
     let ast = parser
         .parse_class()
-        .map_err(|e| anyhow::anyhow!("Parsing error in file {}: {}", file_path, e))?;
+        .map_err(|errors| failure(&render_all(errors, file_path, &source)))?;
 
     let mut output = StringOutput::new();
     let mut ast_printer = ParseTreePrinter::default();
@@ -40,30 +46,161 @@ pub fn analyze_file(file_path: &str, output_dir: Option<&str>) -> Result<(), any
     Ok(())
 }
 
-pub fn compile_file(file_path: &str, output_dir: Option<&str>) -> Result<(), anyhow::Error> {
-    let mut stream = StringCharStream::new_from_file(file_path)?;
-    let mut lexer = Lexer::new(&mut stream);
-    let mut parser = Parser::new(&mut lexer);
-
-    let parse_tree = parser
-        .parse_class()
-        .map_err(|e| anyhow::anyhow!("Parsing error in file {}: {}", file_path, e))?;
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CompileOptions {
+    pub language: LanguageLevel,
+    pub precedence: Precedence,
+}
 
-    let ast = parse_tree_converter::convert_class(&parse_tree)
-        .map_err(|e| anyhow::anyhow!("ParseTree -> AST conversion error in file {}: {}", file_path, e))?;
+pub fn compile_file(file_path: &str, output_dir: Option<&str>, options: &CompileOptions) -> Result<(), anyhow::Error> {
+    compile_files(&[file_path.to_string()], output_dir, options)
+}
 
-    let mut compiler = compiler::Compiler::new();
-    compiler.compile_class(&ast);
-    let vm_code = compiler.get_vm_code();
+/// Compiles the given files as one program.
+///
+/// All classes are checked before any VM code is written. The checker also
+/// knows the classes of the other `.jack` files in the same directories, so a
+/// single file can be compiled on its own; siblings that do not parse are
+/// skipped with a warning. Errors are reported with the
+/// offending source lines; warnings are printed to stderr.
+pub fn compile_files(
+    file_paths: &[String],
+    output_dir: Option<&str>,
+    options: &CompileOptions,
+) -> Result<(), anyhow::Error> {
+    let mut sources = vec![];
+    let mut classes = vec![];
+    let mut errors = vec![];
+    for file_path in file_paths {
+        let source = std::fs::read_to_string(file_path)?;
+        let (class, warnings) = parse_source(&source, options);
+        for warning in render_all(warnings, file_path, &source) {
+            eprintln!("{warning}");
+        }
+        match class {
+            Ok(class) => classes.push(class),
+            Err(diagnostics) => errors.extend(render_all(diagnostics, file_path, &source)),
+        }
+        sources.push(source);
+    }
+    if !errors.is_empty() {
+        return Err(failure(&errors));
+    }
+
+    let (mut known_classes, notes) = sibling_classes(file_paths, options);
+    for note in notes {
+        eprintln!("{note}");
+    }
+    known_classes.extend(classes.iter().cloned());
+    let signatures = ProgramSignatures::new(&known_classes);
+
+    let render = |file_path: &str, source: &str, class_errors: Vec<compiler::CompileError>| {
+        render_all(class_errors.iter().map(|e| e.to_diagnostic()), file_path, source)
+    };
 
-    let (outfile_base_name, output_dir) = derive_base_and_dir(file_path, output_dir)?;
-    let output_file_path = format!("{}/{}.vm", &output_dir, &outfile_base_name);
-    std::fs::write(output_file_path.clone(), vm_code)?;
-    println!("Successfully compiled file {}. VM code written to {}", file_path, output_file_path);
+    for ((file_path, source), class) in file_paths.iter().zip(&sources).zip(&classes) {
+        if let Err(class_errors) = checker::check_class(class, &signatures) {
+            errors.extend(render(file_path, source, class_errors));
+        }
+    }
+    if !errors.is_empty() {
+        return Err(failure(&errors));
+    }
+
+    let mut vm_codes = vec![];
+    for ((file_path, source), class) in file_paths.iter().zip(&sources).zip(&classes) {
+        let mut compiler = compiler::Compiler::new();
+        compiler.set_program_constants(signatures.constants().to_vec());
+        match compiler.compile_class(class) {
+            Ok(()) => vm_codes.push(compiler.get_vm_code()),
+            Err(class_errors) => errors.extend(render(file_path, source, class_errors)),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(failure(&errors));
+    }
+
+    for (file_path, vm_code) in file_paths.iter().zip(vm_codes) {
+        let (outfile_base_name, output_dir) = derive_base_and_dir(file_path, output_dir)?;
+        let output_file_path = format!("{}/{}.vm", &output_dir, &outfile_base_name);
+        std::fs::write(output_file_path.clone(), vm_code)?;
+        println!("Successfully compiled file {}. VM code written to {}", file_path, output_file_path);
+    }
 
     Ok(())
 }
 
+/// Parses the other `.jack` files in the directories of `file_paths`, so calls
+/// into their classes can be checked. Siblings that cannot be read or do not
+/// parse are skipped and left to their own compilation; the returned warnings
+/// name them.
+fn sibling_classes(file_paths: &[String], options: &CompileOptions) -> (Vec<ast::Class>, Vec<String>) {
+    let mut directories: Vec<String> = vec![];
+    for file_path in file_paths {
+        let directory = Path::new(file_path)
+            .parent()
+            .map(|p| p.to_string_lossy().into_owned())
+            .filter(|p| !p.is_empty())
+            .unwrap_or_else(|| ".".to_string());
+        if !directories.contains(&directory) {
+            directories.push(directory);
+        }
+    }
+
+    let mut classes = vec![];
+    let mut warnings = vec![];
+    for sibling in directories.iter().flat_map(|directory| get_jack_files(directory)) {
+        if file_paths.iter().any(|f| Path::new(f) == Path::new(&sibling)) {
+            continue;
+        }
+        let skipped = |reason: String| {
+            Diagnostic::warning(format!("skipping {sibling}, {reason}"))
+                .with_file(&sibling)
+                .with_note("calls into its class cannot be checked and are reported as unknown")
+        };
+        match std::fs::read_to_string(&sibling) {
+            Ok(source) => match parse_source(&source, options) {
+                (Ok(class), _) => classes.push(class),
+                (Err(errors), _) => {
+                    let reason = format!("it has {} syntax error{}", errors.len(), if errors.len() == 1 { "" } else { "s" });
+                    warnings.push(skipped(reason).render(&source));
+                }
+            },
+            Err(e) => warnings.push(skipped(format!("it cannot be read: {e}")).render("")),
+        }
+    }
+    (classes, warnings)
+}
+
+/// Returns the class or its errors, and the warnings.
+fn parse_source(source: &str, options: &CompileOptions) -> (Result<ast::Class, Vec<Diagnostic>>, Vec<Diagnostic>) {
+    let mut stream = StringCharStream::new(source);
+    let mut lexer = Lexer::new(&mut stream);
+    lexer.set_language_level(options.language);
+    let mut parser = Parser::new(&mut lexer);
+    parser.set_language_level(options.language);
+    parser.set_precedence(options.precedence);
+    let class = parser.create_class_ast();
+    (class, parser.take_warnings())
+}
+
+fn render_all(diagnostics: impl IntoIterator<Item = Diagnostic>, file_path: &str, source: &str) -> Vec<String> {
+    diagnostics
+        .into_iter()
+        .map(|diagnostic| diagnostic.with_file(file_path).render(source))
+        .collect()
+}
+
+/// Combines rendered diagnostics into one error.
+fn failure(rendered_diagnostics: &[String]) -> anyhow::Error {
+    let count = rendered_diagnostics.len();
+    anyhow::anyhow!(
+        "{}\naborting due to {count} previous error{}",
+        rendered_diagnostics.join("\n"),
+        if count == 1 { "" } else { "s" }
+    )
+}
+
 fn derive_base_and_dir(file_path: &str, output_dir: Option<&str>) -> Result<(String, String), anyhow::Error> {
     let outfile_base_name = Path::new(file_path)
         .file_stem()
@@ -82,4 +219,29 @@ fn derive_base_and_dir(file_path: &str, output_dir: Option<&str>) -> Result<(Str
     };
 
     Ok((outfile_base_name.to_string(), output_dir))
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sibling_classes() {
+        let dir = std::env::temp_dir().join(format!("jack-siblings-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("Main.jack"), "class Main {}\n").unwrap();
+        std::fs::write(dir.join("Game.jack"), "class Game {}\n").unwrap();
+        std::fs::write(dir.join("Ball.jack"), "class Ball {}\n").unwrap();
+        std::fs::write(dir.join("Broken.jack"), "class Broken {\n").unwrap();
+        let main = dir.join("Main.jack").to_string_lossy().into_owned();
+
+        let (classes, warnings) = sibling_classes(&[main], &CompileOptions::default());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let names: Vec<&str> = classes.iter().map(|class| class.name.as_str()).collect();
+        assert_eq!(names, vec!["Ball", "Game"]);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with(&format!("warning: skipping {}, it has 1 syntax error\n", dir.join("Broken.jack").display())));
+        assert!(warnings[0].ends_with("= note: calls into its class cannot be checked and are reported as unknown\n"));
+    }
+}
@@ -1,17 +1,70 @@
 // Abstract Syntax Tree (AST) definitions for the Jack programming language.
 
+use std::fmt;
+
+/// Line and column of the token a node was converted from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SourcePosition {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for SourcePosition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Class {
     pub name: String,
     pub class_var_declarations: Vec<ClassVarDec>,
+    pub constant_declarations: Vec<ConstantDec>,
+    pub enum_declarations: Vec<EnumDec>,
     pub subroutine_declarations: Vec<SubroutineDec>,
 }
 
+impl Class {
+    /// The constants of the class and the members of its enums, named like
+    /// `MAX` and `Direction.UP`.
+    pub fn constants(&self) -> Vec<ConstantDec> {
+        let enum_members = self.enum_declarations.iter().flat_map(|enum_dec| {
+            enum_dec.members.iter().zip(0..).map(|(member, value)| ConstantDec {
+                name: format!("{}.{}", enum_dec.name, member),
+                var_type: Type::Int,
+                value,
+                position: enum_dec.position,
+            })
+        });
+        self.constant_declarations.iter().cloned().chain(enum_members).collect()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ClassVarDec {
     pub category: ClassVarCategory,
     pub var_type: Type,
     pub names: Vec<String>,
+    /// Position of the type.
+    pub position: SourcePosition,
+}
+
+/// A class constant, which is replaced by its value wherever it is used.
+#[derive(Debug, Clone)]
+pub struct ConstantDec {
+    pub name: String,
+    pub var_type: Type,
+    pub value: i16,
+    /// Position of the type, or of the enum name for enum members.
+    pub position: SourcePosition,
+}
+
+/// An enum, whose members are the int constants 0, 1, 2 and so on.
+#[derive(Debug, Clone)]
+pub struct EnumDec {
+    pub name: String,
+    pub members: Vec<String>,
+    pub position: SourcePosition,
 }
 
 #[derive(Debug, Clone)]
@@ -33,8 +86,11 @@ pub enum ClassVarCategory {
 pub struct SubroutineDec {
     pub category: SubroutineCategory,
     pub return_type: Option<Type>,
+    /// Position of the return type or `void`.
+    pub position: SourcePosition,
     pub name: String,
-    pub parameters: Vec<(Type, String)>,
+    /// Type, name and position of the type of every parameter.
+    pub parameters: Vec<(Type, String, SourcePosition)>,
     pub body: Body,
 }
 
@@ -55,12 +111,15 @@ pub enum SubroutineCategory {
 pub struct VarDec {
     pub var_type: Type,
     pub names: Vec<String>,
+    /// Position of the type.
+    pub position: SourcePosition,
 }
 
 #[derive(Debug, Clone)]
 pub enum Statement {
     Let {
         var_name: String,
+        position: SourcePosition,
         index_expression: Option<Expression>,
         value_expression: Expression,
     },
@@ -79,6 +138,21 @@ pub enum Statement {
     Return {
         value: Option<Expression>,
     },
+    /// Extended Jack only; `initialization` and `step` are let or do statements.
+    For {
+        initialization: Option<Box<Statement>>,
+        condition: Option<Expression>,
+        step: Option<Box<Statement>>,
+        body_statements: Vec<Statement>,
+    },
+    /// Extended Jack only.
+    Break {
+        position: SourcePosition,
+    },
+    /// Extended Jack only.
+    Continue {
+        position: SourcePosition,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -92,9 +166,13 @@ pub enum Term {
     IntegerConstant(u16),
     StringConstant(String),
     KeywordConstant(KeywordConstant),
-    VarName(String),
+    VarName {
+        name: String,
+        position: SourcePosition,
+    },
     VarNameWithIndex {
         var_name: String,
+        position: SourcePosition,
         index_expression: Box<Expression>,
     },
     ExpressionInParens(Box<Expression>),
@@ -135,6 +213,8 @@ pub enum KeywordConstant {
 #[derive(Debug, Clone)]
 pub struct SubroutineCall {
     pub class_or_instance_name: Option<String>,
+    /// Position of the first name of the call.
+    pub position: SourcePosition,
     pub subroutine_name: String,
     pub arguments: Vec<Expression>,
 }
\ No newline at end of file
@@ -0,0 +1,873 @@
+use crate::jack::ast::*;
+use crate::jack::compiler::CompileError;
+use crate::jack::os_api;
+use crate::jack::symbol_table::{SymbolTable, SymbolTableEntry, SymbolTableRef};
+use crate::vmtrans::ast::Segment;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone)]
+pub struct SubroutineSignature {
+    pub category: SubroutineCategory,
+    pub return_type: Option<Type>,
+    pub parameters: Vec<Type>,
+}
+
+/// The subroutines of all classes a program can use: its own classes and the
+/// OS classes it does not define itself, and the constants of its classes.
+pub struct ProgramSignatures {
+    classes: HashMap<String, HashMap<String, SubroutineSignature>>,
+    constants: Vec<ConstantDec>,
+}
+
+impl ProgramSignatures {
+    pub fn new(classes: &[Class]) -> Self {
+        let mut signatures = Self {
+            classes: HashMap::new(),
+            constants: vec![],
+        };
+        for class in os_api::os_classes() {
+            signatures.add_class(&class);
+        }
+        for class in classes {
+            signatures.add_class(class);
+        }
+        signatures
+    }
+
+    fn add_class(&mut self, class: &Class) {
+        let subroutines = class
+            .subroutine_declarations
+            .iter()
+            .map(|subroutine| {
+                let signature = SubroutineSignature {
+                    category: subroutine.category.clone(),
+                    return_type: subroutine.return_type.clone(),
+                    parameters: subroutine.parameters.iter().map(|(t, _, _)| t.clone()).collect(),
+                };
+                (subroutine.name.clone(), signature)
+            })
+            .collect();
+        self.classes.insert(class.name.clone(), subroutines);
+        self.constants.extend(class.constants().into_iter().map(|constant| ConstantDec {
+            name: format!("{}.{}", class.name, constant.name),
+            ..constant
+        }));
+    }
+
+    /// The constants of all classes, named like `Config.MAX`.
+    pub fn constants(&self) -> &[ConstantDec] {
+        &self.constants
+    }
+
+    fn has_class(&self, name: &str) -> bool {
+        self.classes.contains_key(name)
+    }
+
+    fn subroutine(&self, class_name: &str, name: &str) -> Option<&SubroutineSignature> {
+        self.classes.get(class_name)?.get(name)
+    }
+}
+
+/// Type of an expression as far as the checker can tell.
+#[derive(Debug, Clone, PartialEq)]
+enum ValueType {
+    Int,
+    Char,
+    Boolean,
+    Class(String),
+    Null,
+    /// Result of a subroutine without return value
+    Void,
+    /// Array elements and expressions that already produced an error
+    Unknown,
+}
+
+impl ValueType {
+    fn from_type(var_type: &Type) -> Self {
+        match var_type {
+            Type::Int => ValueType::Int,
+            Type::Char => ValueType::Char,
+            Type::Boolean => ValueType::Boolean,
+            Type::Class(name) => ValueType::Class(name.clone()),
+        }
+    }
+
+    fn name(&self) -> String {
+        match self {
+            ValueType::Int => "int".to_string(),
+            ValueType::Char => "char".to_string(),
+            ValueType::Boolean => "boolean".to_string(),
+            ValueType::Class(name) => name.clone(),
+            ValueType::Null => "null".to_string(),
+            ValueType::Void => "void".to_string(),
+            ValueType::Unknown => "unknown".to_string(),
+        }
+    }
+
+    fn is_numeric(&self) -> bool {
+        matches!(self, ValueType::Int | ValueType::Char | ValueType::Unknown)
+    }
+
+    fn is_array(&self) -> bool {
+        matches!(self, ValueType::Unknown) || *self == ValueType::Class("Array".to_string())
+    }
+
+    /// Whether a value of this type may be stored in a variable of type `target`.
+    ///
+    /// Jack is weakly typed: `int`, `char` and `boolean` mix freely, objects
+    /// are addresses and can be used as `int` or `char`, an `int` is accepted
+    /// as an address for every object type, and `Array` serves as the untyped
+    /// pointer: it accepts every object and is accepted for every object type.
+    /// Only objects of unrelated classes and objects stored in `boolean`
+    /// variables are rejected.
+    fn is_assignable_to(&self, target: &Type) -> bool {
+        match (target, self) {
+            (_, ValueType::Unknown) => true,
+            (Type::Int | Type::Char, value) => *value != ValueType::Void,
+            (Type::Boolean, value) => value.is_numeric() || *value == ValueType::Boolean,
+            (Type::Class(name), value) if name == "Array" => {
+                matches!(value, ValueType::Int | ValueType::Null | ValueType::Class(_))
+            }
+            (Type::Class(name), value) => {
+                matches!(value, ValueType::Int | ValueType::Null)
+                    || value.is_array()
+                    || *value == ValueType::Class(name.clone())
+            }
+        }
+    }
+}
+
+fn type_name(var_type: &Type) -> String {
+    ValueType::from_type(var_type).name()
+}
+
+/// Checks a class before it is compiled.
+///
+/// Every variable, class and subroutine name has to resolve, operators need
+/// operands of matching types, assigned values, arguments and returned values
+/// have to fit the declared types and calls have to match the number of
+/// parameters. Fields and `this` are only available in methods and
+/// constructors. Errors carry the position of the innermost variable, call or
+/// `let` statement they were found in.
+pub fn check_class(class: &Class, signatures: &ProgramSignatures) -> Result<(), Vec<CompileError>> {
+    let mut checker = Checker {
+        signatures,
+        class_name: class.name.clone(),
+        subroutine: None,
+        symbols: SymbolTable::new_ref(None),
+        position: None,
+        errors: vec![],
+    };
+    checker.check_class(class);
+
+    if checker.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(checker.errors)
+    }
+}
+
+struct Checker<'a> {
+    signatures: &'a ProgramSignatures,
+    class_name: String,
+    subroutine: Option<SubroutineDec>,
+    symbols: SymbolTableRef,
+    position: Option<SourcePosition>,
+    errors: Vec<CompileError>,
+}
+
+impl Checker<'_> {
+    fn check_class(&mut self, class: &Class) {
+        for constant in self.signatures.constants() {
+            self.symbols
+                .borrow_mut()
+                .add_constant(constant.name.clone(), constant.var_type.clone(), constant.value);
+        }
+        // Constants, enum members and class variables share one namespace
+        let mut class_names = HashSet::new();
+        for constant in class.constants() {
+            if let Type::Class(type_name) = &constant.var_type {
+                self.error_at(
+                    constant.position,
+                    format!("constant {} cannot be of type {type_name}, only int, char and boolean", constant.name),
+                );
+            }
+            if !class_names.insert(constant.name.clone()) {
+                self.error_at(constant.position, format!("duplicate declaration of {}", constant.name));
+            }
+            self.symbols
+                .borrow_mut()
+                .add_constant(constant.name, constant.var_type, constant.value);
+        }
+
+        for class_var_decl in &class.class_var_declarations {
+            self.check_type(&class_var_decl.var_type, class_var_decl.position);
+            for name in &class_var_decl.names {
+                if !class_names.insert(name.clone()) {
+                    self.error_at(class_var_decl.position, format!("duplicate declaration of {name}"));
+                }
+                self.symbols.borrow_mut().add_class_var(
+                    name.clone(),
+                    class_var_decl.category.clone(),
+                    class_var_decl.var_type.clone(),
+                );
+            }
+        }
+
+        let class_symbols = self.symbols.clone();
+        for subroutine_decl in &class.subroutine_declarations {
+            self.symbols = SymbolTable::new_ref(Some(class_symbols.clone()));
+            self.subroutine = Some(subroutine_decl.clone());
+            self.check_subroutine(subroutine_decl);
+        }
+        self.symbols = class_symbols;
+        self.subroutine = None;
+    }
+
+    fn check_subroutine(&mut self, subroutine_decl: &SubroutineDec) {
+        if let Some(return_type) = &subroutine_decl.return_type {
+            self.check_type(return_type, subroutine_decl.position);
+        }
+        if subroutine_decl.category == SubroutineCategory::Method {
+            self.symbols
+                .borrow_mut()
+                .add_parameter("this".to_string(), Type::Class(self.class_name.clone()));
+        }
+        for (param_type, param_name, position) in &subroutine_decl.parameters {
+            self.check_type(param_type, *position);
+            self.symbols.borrow_mut().add_parameter(param_name.clone(), param_type.clone());
+        }
+        for var_decl in &subroutine_decl.body.var_declarations {
+            self.check_type(&var_decl.var_type, var_decl.position);
+            for name in &var_decl.names {
+                self.symbols.borrow_mut().add_local(name.clone(), var_decl.var_type.clone());
+            }
+        }
+
+        for statement in &subroutine_decl.body.statements {
+            self.check_statement(statement);
+        }
+    }
+
+    fn check_type(&mut self, var_type: &Type, position: SourcePosition) {
+        if let Type::Class(name) = var_type
+            && !self.signatures.has_class(name)
+        {
+            self.error_at(position, format!("unknown type {name}"));
+        }
+    }
+
+    fn check_statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Let {
+                var_name,
+                position,
+                index_expression,
+                value_expression,
+            } => {
+                let outer_position = self.position.replace(*position);
+                self.check_let_statement(var_name, index_expression, value_expression);
+                self.position = outer_position;
+            }
+            Statement::If {
+                condition,
+                if_statements,
+                else_statements,
+            } => {
+                self.expression_type(condition);
+                for statement in if_statements.iter().chain(else_statements.iter().flatten()) {
+                    self.check_statement(statement);
+                }
+            }
+            Statement::While {
+                condition,
+                body_statements,
+            } => {
+                self.expression_type(condition);
+                for statement in body_statements {
+                    self.check_statement(statement);
+                }
+            }
+            Statement::Do { subroutine_call } => {
+                self.call_type(subroutine_call);
+            }
+            Statement::Return { value } => self.check_return(value),
+            Statement::For {
+                initialization,
+                condition,
+                step,
+                body_statements,
+            } => {
+                if let Some(initialization) = initialization {
+                    self.check_statement(initialization);
+                }
+                if let Some(condition) = condition {
+                    self.expression_type(condition);
+                }
+                if let Some(step) = step {
+                    self.check_statement(step);
+                }
+                for statement in body_statements {
+                    self.check_statement(statement);
+                }
+            }
+            // Loop nesting is checked while generating code
+            Statement::Break { .. } | Statement::Continue { .. } => {}
+        }
+    }
+
+    fn check_let_statement(
+        &mut self,
+        var_name: &str,
+        index_expression: &Option<Expression>,
+        value_expression: &Expression,
+    ) {
+        let entry = self.resolve_variable(var_name);
+        let value_type = self.expression_type(value_expression);
+        let Some(entry) = entry else {
+            return;
+        };
+        match index_expression {
+            None if entry.segment == Segment::Constant => {
+                self.error(format!("cannot assign to constant {var_name}"));
+            }
+            Some(index_expression) => {
+                self.check_indexing(var_name, &entry, index_expression);
+            }
+            None => {
+                if !value_type.is_assignable_to(&entry.var_type) {
+                    self.error(format!(
+                        "cannot assign {} to {var_name} of type {}",
+                        value_type.name(),
+                        type_name(&entry.var_type)
+                    ));
+                }
+            }
+        }
+    }
+
+    fn check_return(&mut self, value: &Option<Expression>) {
+        let return_type = self.subroutine.as_ref().and_then(|s| s.return_type.clone());
+        match (value, return_type) {
+            (None, None) => {}
+            (Some(value), None) => {
+                self.expression_type(value);
+                self.error("void subroutine cannot return a value".to_string());
+            }
+            (None, Some(return_type)) => {
+                self.error(format!("missing return value of type {}", type_name(&return_type)));
+            }
+            (Some(value), Some(return_type)) => {
+                let value_type = self.expression_type(value);
+                if !value_type.is_assignable_to(&return_type) {
+                    self.error(format!(
+                        "cannot return {} from a subroutine returning {}",
+                        value_type.name(),
+                        type_name(&return_type)
+                    ));
+                }
+            }
+        }
+    }
+
+    fn check_indexing(&mut self, var_name: &str, entry: &SymbolTableEntry, index_expression: &Expression) {
+        if !ValueType::from_type(&entry.var_type).is_array() {
+            self.error(format!("{var_name} of type {} cannot be indexed", type_name(&entry.var_type)));
+        }
+        let index_type = self.expression_type(index_expression);
+        if !index_type.is_numeric() {
+            self.error(format!("index into {var_name} must be int, found {}", index_type.name()));
+        }
+    }
+
+    fn expression_type(&mut self, expression: &Expression) -> ValueType {
+        let mut result = self.term_type(&expression.term);
+        for (operator, term) in &expression.rest {
+            let right = self.term_type(term);
+            result = self.operator_type(operator, result, right);
+        }
+        result
+    }
+
+    fn operator_type(&mut self, operator: &Operator, left: ValueType, right: ValueType) -> ValueType {
+        let (symbol, result) = match operator {
+            // Addresses may be offset, so arrays take part in additions
+            Operator::Plus | Operator::Minus => {
+                let operand = |t: &ValueType| t.is_numeric() || t.is_array();
+                let symbol = if matches!(operator, Operator::Plus) { "+" } else { "-" };
+                (symbol, (operand(&left) && operand(&right)).then_some(ValueType::Int))
+            }
+            Operator::Multiply | Operator::Divide => {
+                let symbol = if matches!(operator, Operator::Multiply) { "*" } else { "/" };
+                (symbol, (left.is_numeric() && right.is_numeric()).then_some(ValueType::Int))
+            }
+            Operator::LessThan | Operator::GreaterThan => {
+                let symbol = if matches!(operator, Operator::LessThan) { "<" } else { ">" };
+                (symbol, (left.is_numeric() && right.is_numeric()).then_some(ValueType::Boolean))
+            }
+            // Bitwise on ints, logical on booleans, and both mix freely
+            Operator::And | Operator::Or => {
+                let symbol = if matches!(operator, Operator::And) { "&" } else { "|" };
+                let operand = |t: &ValueType| t.is_numeric() || *t == ValueType::Boolean;
+                let result = match (&left, &right) {
+                    (ValueType::Boolean | ValueType::Unknown, ValueType::Boolean)
+                    | (ValueType::Boolean, ValueType::Unknown) => Some(ValueType::Boolean),
+                    _ if operand(&left) && operand(&right) => Some(ValueType::Int),
+                    _ => None,
+                };
+                (symbol, result)
+            }
+            Operator::Equal => ("=", Some(ValueType::Boolean)),
+        };
+
+        result.unwrap_or_else(|| {
+            self.error(format!(
+                "operator {symbol} cannot be applied to {} and {}",
+                left.name(),
+                right.name()
+            ));
+            ValueType::Unknown
+        })
+    }
+
+    fn term_type(&mut self, term: &Term) -> ValueType {
+        match term {
+            Term::IntegerConstant(_) => ValueType::Int,
+            Term::StringConstant(_) => ValueType::Class("String".to_string()),
+            Term::KeywordConstant(KeywordConstant::True | KeywordConstant::False) => ValueType::Boolean,
+            Term::KeywordConstant(KeywordConstant::Null) => ValueType::Null,
+            Term::KeywordConstant(KeywordConstant::This) => {
+                if self.in_function() {
+                    self.error("this cannot be used in a function".to_string());
+                    ValueType::Unknown
+                } else {
+                    ValueType::Class(self.class_name.clone())
+                }
+            }
+            Term::VarName { name, position } => {
+                let outer_position = self.position.replace(*position);
+                let entry = self.resolve_variable(name);
+                self.position = outer_position;
+                match entry {
+                    Some(entry) => ValueType::from_type(&entry.var_type),
+                    None => ValueType::Unknown,
+                }
+            }
+            Term::VarNameWithIndex {
+                var_name,
+                position,
+                index_expression,
+            } => {
+                let outer_position = self.position.replace(*position);
+                match self.resolve_variable(var_name) {
+                    Some(entry) => self.check_indexing(var_name, &entry, index_expression),
+                    None => {
+                        self.expression_type(index_expression);
+                    }
+                }
+                self.position = outer_position;
+                ValueType::Unknown
+            }
+            Term::ExpressionInParens(expression) => self.expression_type(expression),
+            Term::UnaryOp { operator, term } => {
+                let operand = self.term_type(term);
+                match operator {
+                    UnaryOperator::Negate if operand.is_numeric() => ValueType::Int,
+                    UnaryOperator::Not if operand == ValueType::Boolean => ValueType::Boolean,
+                    UnaryOperator::Not if operand.is_numeric() => ValueType::Int,
+                    _ => {
+                        let symbol = if matches!(operator, UnaryOperator::Negate) { "-" } else { "~" };
+                        self.error(format!("operator {symbol} cannot be applied to {}", operand.name()));
+                        ValueType::Unknown
+                    }
+                }
+            }
+            Term::SubroutineCall(call) => {
+                let result = self.call_type(call);
+                if result == ValueType::Void {
+                    let outer_position = self.position.replace(call.position);
+                    self.error(format!("{} does not return a value", call_name(call)));
+                    self.position = outer_position;
+                    return ValueType::Unknown;
+                }
+                result
+            }
+        }
+    }
+
+    /// Checks a call and returns the type of its result.
+    fn call_type(&mut self, call: &SubroutineCall) -> ValueType {
+        let outer_position = self.position.replace(call.position);
+        let result = self.check_call(call);
+        self.position = outer_position;
+        result
+    }
+
+    fn check_call(&mut self, call: &SubroutineCall) -> ValueType {
+        let argument_types: Vec<ValueType> = call
+            .arguments
+            .iter()
+            .map(|argument| self.expression_type(argument))
+            .collect();
+
+        let Some((class_name, signature)) = self.resolve_subroutine(call) else {
+            return ValueType::Unknown;
+        };
+        let full_name = format!("{class_name}.{}", call.subroutine_name);
+
+        if argument_types.len() != signature.parameters.len() {
+            self.error(format!(
+                "{full_name} expects {} argument{}, found {}",
+                signature.parameters.len(),
+                if signature.parameters.len() == 1 { "" } else { "s" },
+                argument_types.len()
+            ));
+        } else {
+            for (i, (argument_type, parameter_type)) in argument_types.iter().zip(&signature.parameters).enumerate() {
+                if !argument_type.is_assignable_to(parameter_type) {
+                    self.error(format!(
+                        "argument {} of {full_name} must be {}, found {}",
+                        i + 1,
+                        type_name(parameter_type),
+                        argument_type.name()
+                    ));
+                }
+            }
+        }
+
+        match &signature.return_type {
+            Some(return_type) => ValueType::from_type(return_type),
+            None => ValueType::Void,
+        }
+    }
+
+    /// Finds the class and signature of the called subroutine and checks
+    /// that methods are called on objects and functions on classes.
+    fn resolve_subroutine(&mut self, call: &SubroutineCall) -> Option<(String, SubroutineSignature)> {
+        let subroutine_name = &call.subroutine_name;
+        let (class_name, on_object) = match &call.class_or_instance_name {
+            None => (self.class_name.clone(), true),
+            Some(name) if self.symbols.borrow().get_entry(name).is_some() => {
+                let entry = self.resolve_variable(name)?;
+                match &entry.var_type {
+                    Type::Class(class_name) if self.signatures.has_class(class_name) => (class_name.clone(), true),
+                    // Unknown classes have been reported with the declaration
+                    Type::Class(_) => return None,
+                    var_type => {
+                        self.error(format!("{name} of type {} has no methods", type_name(var_type)));
+                        return None;
+                    }
+                }
+            }
+            Some(name) if self.signatures.has_class(name) => (name.clone(), false),
+            Some(name) => {
+                self.error(format!("unknown class or variable {name}"));
+                return None;
+            }
+        };
+
+        let Some(signature) = self.signatures.subroutine(&class_name, subroutine_name).cloned() else {
+            self.error(format!("unknown subroutine {class_name}.{subroutine_name}"));
+            return None;
+        };
+        let is_method = signature.category == SubroutineCategory::Method;
+        match (call.class_or_instance_name.is_some(), on_object, is_method) {
+            (false, _, true) if self.in_function() => {
+                self.error(format!("method {class_name}.{subroutine_name} cannot be called from a function"));
+            }
+            (false, _, false) => {
+                self.error(format!("{class_name}.{subroutine_name} is not a method and must be called as {class_name}.{subroutine_name}"));
+            }
+            (true, true, false) => {
+                self.error(format!("{class_name}.{subroutine_name} is not a method and cannot be called on an object"));
+            }
+            (true, false, true) => {
+                self.error(format!("method {class_name}.{subroutine_name} must be called on an object"));
+            }
+            _ => {}
+        }
+        Some((class_name, signature))
+    }
+
+    fn resolve_variable(&mut self, name: &str) -> Option<SymbolTableEntry> {
+        let entry = self.symbols.borrow().get_entry(name);
+        match entry {
+            None => {
+                self.error(format!("unknown variable {name}"));
+                None
+            }
+            Some(entry) if entry.segment == Segment::This && self.in_function() => {
+                self.error(format!("field {name} cannot be used in a function"));
+                None
+            }
+            Some(entry) => Some(entry),
+        }
+    }
+
+    fn in_function(&self) -> bool {
+        self.subroutine
+            .as_ref()
+            .is_some_and(|s| s.category == SubroutineCategory::Function)
+    }
+
+    fn error_at(&mut self, position: SourcePosition, message: String) {
+        let outer_position = self.position.replace(position);
+        self.error(message);
+        self.position = outer_position;
+    }
+
+    fn error(&mut self, message: String) {
+        self.errors.push(CompileError {
+            class_name: self.class_name.clone(),
+            subroutine_name: self.subroutine.as_ref().map(|s| s.name.clone()),
+            position: self.position,
+            message,
+        });
+    }
+}
+
+fn call_name(call: &SubroutineCall) -> String {
+    match &call.class_or_instance_name {
+        Some(name) => format!("{name}.{}", call.subroutine_name),
+        None => call.subroutine_name.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammarous::StringCharStream;
+    use crate::jack::{LanguageLevel, Lexer, Parser};
+
+    #[test]
+    fn test_check_constants() {
+        let config = parse_extended_class("class Config {\n    enum Mode { SLOW, FAST }\n}\n");
+        let main = parse_extended_class(
+            "class Main {\n    const int MAX = 10;\n    const Array EMPTY = null;\n\n    function void main() {\n        var int mode;\n        let mode = Config.Mode.FAST + MAX;\n        let mode = Config.Mode.FASTER;\n        let MAX = 1;\n        return;\n    }\n}\n",
+        );
+        let signatures = ProgramSignatures::new(&[config, main.clone()]);
+
+        let errors: Vec<String> = check_class(&main, &signatures)
+            .unwrap_err()
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+
+        assert_eq!(
+            errors,
+            vec![
+                "Main (line 3, column 11): constant EMPTY cannot be of type Array, only int, char and boolean",
+                "Main.main (line 8, column 20): unknown variable Config.Mode.FASTER",
+                "Main.main (line 9, column 13): cannot assign to constant MAX",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_duplicate_class_names() {
+        let main = parse_extended_class(
+            "class Main {\n    const int MAX = 10;\n    static int MAX, count;\n    field int count;\n    enum E { A, B, A }\n}\n",
+        );
+        let signatures = ProgramSignatures::new(std::slice::from_ref(&main));
+
+        let errors: Vec<String> = check_class(&main, &signatures)
+            .unwrap_err()
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+
+        assert_eq!(
+            errors,
+            vec![
+                "Main (line 5, column 10): duplicate declaration of E.A",
+                "Main (line 3, column 12): duplicate declaration of MAX",
+                "Main (line 4, column 11): duplicate declaration of count",
+            ]
+        );
+    }
+
+    fn parse_class(code: &str) -> Class {
+        let mut stream = StringCharStream::new(code);
+        let mut lexer = Lexer::new(&mut stream);
+        let mut parser = Parser::new(&mut lexer);
+        parser.create_class_ast().unwrap()
+    }
+
+    fn parse_extended_class(code: &str) -> Class {
+        let mut stream = StringCharStream::new(code);
+        let mut lexer = Lexer::new(&mut stream);
+        lexer.set_language_level(LanguageLevel::Extended);
+        let mut parser = Parser::new(&mut lexer);
+        parser.set_language_level(LanguageLevel::Extended);
+        parser.create_class_ast().unwrap()
+    }
+
+    fn check(code: &str) -> Result<(), Vec<String>> {
+        let class = parse_class(code);
+        let signatures = ProgramSignatures::new(std::slice::from_ref(&class));
+        check_class(&class, &signatures).map_err(|errors| errors.iter().map(|e| e.to_string()).collect())
+    }
+
+    #[test]
+    fn test_check_valid_class() {
+        let code = r#"
+        class Point {
+            field int x, y;
+            static Array cache;
+
+            constructor Point new(int ax, int ay) {
+                let x = ax;
+                let y = ay;
+                return this;
+            }
+
+            method int getX() { return x; }
+
+            method boolean isLeftOf(Point other) {
+                return (x < other.getX()) & ~(other = null);
+            }
+
+            function void main() {
+                var Point p;
+                var String s;
+                var char c;
+                let p = Point.new(1, 2);
+                let cache = Array.new(10);
+                let cache[p.getX()] = p;
+                let p = cache[0];
+                let s = "abc";
+                let c = s.charAt(0) + 1;
+                do Output.printInt(p.getX() * 2);
+                do Memory.deAlloc(p);
+                return;
+            }
+        }
+        "#;
+        assert_eq!(check(code), Ok(()));
+    }
+
+    #[test]
+    fn test_check_errors() {
+        let code = r#"
+        class Main {
+            field int count;
+
+            method void inc(int by) {
+                let count = count + by;
+                return;
+            }
+
+            function int main() {
+                var boolean done;
+                var Main m;
+                let done = "yes";
+                let count = 0;
+                let m = Main.create();
+                do m.inc(true, 2);
+                do inc(1);
+                do Main.inc(1);
+                do Output.printString(5 + done);
+                let m[1] = undefined;
+                return m.inc(1);
+            }
+
+            function void stop(Foo f) {
+                return 1;
+            }
+        }
+        "#;
+        assert_eq!(
+            check(code).unwrap_err(),
+            vec![
+                "Main.main (line 13, column 21): cannot assign String to done of type boolean",
+                "Main.main (line 14, column 21): field count cannot be used in a function",
+                "Main.main (line 15, column 25): unknown subroutine Main.create",
+                "Main.main (line 16, column 20): Main.inc expects 1 argument, found 2",
+                "Main.main (line 17, column 20): method Main.inc cannot be called from a function",
+                "Main.main (line 18, column 20): method Main.inc must be called on an object",
+                "Main.main (line 19, column 20): operator + cannot be applied to int and boolean",
+                "Main.main (line 20, column 28): unknown variable undefined",
+                "Main.main (line 20, column 21): m of type Main cannot be indexed",
+                "Main.main (line 21, column 24): m.inc does not return a value",
+                "Main.stop (line 24, column 32): unknown type Foo",
+                "Main.stop: void subroutine cannot return a value",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_calls_across_classes() {
+        let game = parse_class("class Game {\n    method void move(int dx, int dy) { return; }\n}\n");
+        let main = parse_class(
+            "class Main {\n    function void main() {\n        var Game game;\n        do game.move(1);\n        do Game.move(1, 2);\n        do Output.printStrin(\"x\");\n        do Gmae.new();\n        return;\n    }\n}\n",
+        );
+        let signatures = ProgramSignatures::new(&[game, main.clone()]);
+
+        let errors: Vec<String> = check_class(&main, &signatures)
+            .unwrap_err()
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+
+        assert_eq!(
+            errors,
+            vec![
+                "Main.main (line 4, column 12): Game.move expects 2 arguments, found 1",
+                "Main.main (line 5, column 12): method Game.move must be called on an object",
+                "Main.main (line 6, column 12): unknown subroutine Output.printStrin",
+                "Main.main (line 7, column 12): unknown class or variable Gmae",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_os_calls() {
+        let code = "class Main {\n    function void main() {\n        var String s;\n        do Output.printString(true);\n        let s = String.new();\n        do s.appendChar(65);\n        let s = s.length() < 2;\n        return;\n    }\n}\n";
+        assert_eq!(
+            check(code).unwrap_err(),
+            vec![
+                "Main.main (line 4, column 12): argument 1 of Output.printString must be String, found boolean",
+                "Main.main (line 5, column 17): String.new expects 1 argument, found 0",
+                "Main.main (line 7, column 13): cannot assign boolean to s of type String",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_weak_typing() {
+        let code = r#"
+        class Main {
+            function Main main() {
+                var boolean done;
+                var int count;
+                var String s;
+                var Array a;
+                var Main m;
+                let done = 0;
+                let done = count;
+                let count = done;
+                let s = 0;
+                let m = a;
+                let a = m;
+                let m = count;
+                do Screen.setColor(1);
+                do Memory.deAlloc(s);
+                return 0;
+            }
+
+            method int address(Main p) {
+                var int a;
+                var boolean b;
+                let a = this;
+                let a = Memory.peek(this);
+                do Output.printInt(p);
+                let b = (a & 1) | b;
+                let a = ~b & a;
+                let a = ~(a & 1);
+                return this;
+            }
+        }
+        "#;
+        assert_eq!(check(code), Ok(()));
+    }
+}
@@ -1,4 +1,6 @@
 use std::path::Path;
+use crate::jack::lexer::LanguageLevel;
+use crate::jack::parser::Precedence;
 
 #[derive(clap::Parser, Debug, Clone)]
 #[command(name="JackAnalyzer", version, about="Analyze Jack programs", long_about = None)]
@@ -14,6 +16,20 @@ pub struct CompilerCli {
     pub source: String,
     #[arg(short, long, help = "Output directory for the compiler results")]
     pub output_dir: Option<String>,
+    #[arg(
+        long,
+        value_enum,
+        default_value = "standard",
+        help = "Language level of the sources; --help describes what each level accepts"
+    )]
+    pub language: LanguageLevel,
+    #[arg(
+        long,
+        value_enum,
+        default_value = "left-to-right",
+        help = "Operator precedence; standard makes * and / bind tighter than + and -, then comparisons, then & and |"
+    )]
+    pub precedence: Precedence,
 }
 
 pub fn get_jack_files(source: &str) -> Vec<String> {
@@ -24,25 +40,26 @@ pub fn get_jack_files(source: &str) -> Vec<String> {
         if is_jack_file(path) {
             files.push(source.to_string());
         }
-    } else if path.is_dir() {
-        if let Ok(entries) = std::fs::read_dir(path) {
-            entries
-                .into_iter()
-                .filter_map(|entry| entry.ok())
-                .for_each(|entry| {
-                    let entry_path = entry.path();
-                    if is_jack_file(&entry_path) {
-                        if let Some(file_str) = entry_path.to_str() {
-                            files.push(file_str.to_string());
-                        }
-                    }
-                });
-        }
+    } else if path.is_dir()
+        && let Ok(entries) = std::fs::read_dir(path)
+    {
+        entries
+            .into_iter()
+            .filter_map(|entry| entry.ok())
+            .for_each(|entry| {
+                let entry_path = entry.path();
+                if is_jack_file(&entry_path)
+                    && let Some(file_str) = entry_path.to_str()
+                {
+                    files.push(file_str.to_string());
+                }
+            });
+        files.sort();
     }
 
     files
 }
 
 fn is_jack_file(path: &Path) -> bool {
-    path.extension().map_or(false, |ext| ext == "jack")
+    path.extension().is_some_and(|ext| ext == "jack")
 }
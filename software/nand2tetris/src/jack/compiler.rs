@@ -1,16 +1,64 @@
 use crate::jack::ast::*;
+use crate::jack::diagnostic::{Diagnostic, Span};
 use crate::jack::symbol_table::{SymbolTable, SymbolTableRef};
 use crate::vmtrans::ast::Segment;
 use std::collections::HashMap;
+use std::fmt;
+
+/// A mistake in a Jack class that prevents compiling it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompileError {
+    pub class_name: String,
+    /// Subroutine the error occurred in, `None` for class level errors.
+    pub subroutine_name: Option<String>,
+    pub position: Option<SourcePosition>,
+    pub message: String,
+}
+
+impl CompileError {
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let mut diagnostic = Diagnostic::error(self.message.clone());
+        if let Some(position) = self.position {
+            diagnostic = diagnostic.with_span(Span {
+                line: position.line,
+                column: position.column,
+                length: 0,
+            });
+        }
+        let location = match &self.subroutine_name {
+            Some(subroutine_name) => format!("in {}.{subroutine_name}", self.class_name),
+            None => format!("in class {}", self.class_name),
+        };
+        diagnostic.with_note(location)
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.class_name)?;
+        if let Some(subroutine_name) = &self.subroutine_name {
+            write!(f, ".{subroutine_name}")?;
+        }
+        if let Some(position) = &self.position {
+            write!(f, " ({position})")?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
 
 pub struct Compiler {
     vm_lines: Vec<String>,
     char_map: HashMap<char, u8>,
     curr_class_name: String,
     curr_symbols: Option<SymbolTableRef>,
-    class_symbols: HashMap<String, SymbolTableRef>,
     next_label_num: HashMap<String, usize>,
     curr_subroutine_category: Option<SubroutineCategory>,
+    curr_subroutine_name: Option<String>,
+    /// Labels that `continue` and `break` jump to, for each enclosing loop.
+    loop_labels: Vec<(String, String)>,
+    /// Constants of all classes of the program, named like `Config.MAX`.
+    program_constants: Vec<ConstantDec>,
+    errors: Vec<CompileError>,
 }
 
 impl Compiler {
@@ -20,26 +68,46 @@ impl Compiler {
             char_map: Self::initialize_char_map(),
             curr_class_name: String::new(),
             curr_symbols: None,
-            class_symbols: HashMap::new(),
             next_label_num: HashMap::new(),
             curr_subroutine_category: None,
+            curr_subroutine_name: None,
+            loop_labels: Vec::new(),
+            program_constants: Vec::new(),
+            errors: Vec::new(),
         }
     }
 
+    /// Makes the constants of other classes available by their qualified
+    /// names, like `Config.MAX` or `Config.Direction.UP`.
+    pub fn set_program_constants(&mut self, constants: Vec<ConstantDec>) {
+        self.program_constants = constants;
+    }
+
     pub fn get_vm_code(&self) -> String {
         self.vm_lines.join("\n") + "\n"
     }
 
-    pub fn compile_class(&mut self, class: &Class) {
+    /// Compiles a class into VM code, available from `get_vm_code` on success.
+    pub fn compile_class(&mut self, class: &Class) -> Result<(), Vec<CompileError>> {
         self.vm_lines.clear();
+        self.errors.clear();
         self.curr_class_name = class.name.clone();
         self.next_label_num.clear();
 
         self.curr_symbols = Some(SymbolTable::new_ref(None));
-        self.class_symbols.insert(
-            class.name.clone(),
-            self.curr_symbols.as_ref().unwrap().clone(),
-        );
+
+        let symbols = self.get_current_symbols();
+        for constant in &self.program_constants {
+            symbols
+                .borrow_mut()
+                .add_constant(constant.name.clone(), constant.var_type.clone(), constant.value);
+        }
+        for constant in class.constants() {
+            let qualified_name = format!("{}.{}", class.name, constant.name);
+            let mut symbols = symbols.borrow_mut();
+            symbols.add_constant(qualified_name, constant.var_type.clone(), constant.value);
+            symbols.add_constant(constant.name, constant.var_type, constant.value);
+        }
 
         for class_var_decl in &class.class_var_declarations {
             self.compile_class_var_declaration(class_var_decl);
@@ -56,12 +124,18 @@ impl Compiler {
         }
 
         self.curr_symbols = None;
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
     }
 
     fn compile_subroutine(&mut self, subroutine_decl: &SubroutineDec, num_fields: usize) {
         let subroutine_symbols = SymbolTable::new_ref(Some(self.get_current_symbols()));
         self.curr_symbols = Some(subroutine_symbols.clone());
         self.curr_subroutine_category = Some(subroutine_decl.category.clone());
+        self.curr_subroutine_name = Some(subroutine_decl.name.clone());
 
         if subroutine_decl.category == SubroutineCategory::Method {
             subroutine_symbols.borrow_mut().add_parameter(
@@ -70,7 +144,7 @@ impl Compiler {
             );
         }
 
-        for (param_type, param_name) in &subroutine_decl.parameters {
+        for (param_type, param_name, _) in &subroutine_decl.parameters {
             subroutine_symbols
                 .borrow_mut()
                 .add_parameter(param_name.clone(), param_type.clone());
@@ -111,6 +185,7 @@ impl Compiler {
             .expect("subroutine parent not found");
         self.curr_symbols = Some(parent);
         self.curr_subroutine_category = None;
+        self.curr_subroutine_name = None;
     }
 
     fn compile_subroutine_body(&mut self, body: &Body) {
@@ -133,10 +208,11 @@ impl Compiler {
         match statement {
             Statement::Let {
                 var_name,
+                position,
                 index_expression,
                 value_expression,
             } => {
-                self.compile_let_statement(var_name, index_expression, value_expression);
+                self.compile_let_statement(var_name, *position, index_expression, value_expression);
             }
             Statement::If {
                 condition,
@@ -163,6 +239,26 @@ impl Compiler {
                 }
                 self.vm_write_str("return");
             }
+            Statement::For {
+                initialization,
+                condition,
+                step,
+                body_statements,
+            } => {
+                self.compile_for_statement(initialization, condition, step, body_statements);
+            }
+            Statement::Break { position } => {
+                match self.loop_labels.last() {
+                    Some((_, end_label)) => self.vm_write(format!("goto {}", end_label)),
+                    None => self.error(*position, "break outside of a loop".to_string()),
+                }
+            }
+            Statement::Continue { position } => {
+                match self.loop_labels.last() {
+                    Some((continue_label, _)) => self.vm_write(format!("goto {}", continue_label)),
+                    None => self.error(*position, "continue outside of a loop".to_string()),
+                }
+            }
         }
     }
 
@@ -174,9 +270,44 @@ impl Compiler {
         self.compile_expression(condition);
         self.vm_write_str("not");
         self.vm_write(format!("if-goto {}", end_label));
+        self.loop_labels.push((start_label.clone(), end_label.clone()));
+        for stmt in body_statements {
+            self.compile_statement(stmt);
+        }
+        self.loop_labels.pop();
+        self.vm_write(format!("goto {}", start_label));
+        self.vm_write(format!("label {}", end_label));
+    }
+
+    fn compile_for_statement(
+        &mut self,
+        initialization: &Option<Box<Statement>>,
+        condition: &Option<Expression>,
+        step: &Option<Box<Statement>>,
+        body_statements: &[Statement],
+    ) {
+        let start_label = self.create_label("for");
+        let continue_label = self.create_label("for");
+        let end_label = self.create_label("for");
+
+        if let Some(initialization) = initialization {
+            self.compile_statement(initialization);
+        }
+        self.vm_write(format!("label {}", start_label));
+        if let Some(condition) = condition {
+            self.compile_expression(condition);
+            self.vm_write_str("not");
+            self.vm_write(format!("if-goto {}", end_label));
+        }
+        self.loop_labels.push((continue_label.clone(), end_label.clone()));
         for stmt in body_statements {
             self.compile_statement(stmt);
         }
+        self.loop_labels.pop();
+        self.vm_write(format!("label {}", continue_label));
+        if let Some(step) = step {
+            self.compile_statement(step);
+        }
         self.vm_write(format!("goto {}", start_label));
         self.vm_write(format!("label {}", end_label));
     }
@@ -184,12 +315,13 @@ impl Compiler {
     fn compile_let_statement(
         &mut self,
         var_name: &str,
+        position: SourcePosition,
         index_expression: &Option<Expression>,
         value_expression: &Expression,
     ) {
         match index_expression {
             Some(index_expr) => {
-                self.compile_var_name(var_name);
+                self.compile_var_name(var_name, position);
                 self.compile_expression(index_expr);
                 self.vm_write_str("add");
                 self.compile_expression(value_expression);
@@ -200,8 +332,13 @@ impl Compiler {
             }
             None => {
                 self.compile_expression(value_expression);
-                let (segment_str, index) = self.get_segment_and_index(var_name);
-                self.vm_write(format!("pop {} {}", segment_str, index));
+                match self.get_segment_and_index(var_name, position) {
+                    Some((segment_str, _)) if segment_str == "constant" => {
+                        self.error(position, format!("Cannot assign to constant {var_name}"));
+                    }
+                    Some((segment_str, index)) => self.vm_write(format!("pop {} {}", segment_str, index)),
+                    None => {}
+                }
             }
         }
     }
@@ -249,10 +386,9 @@ impl Compiler {
     }
 
     fn get_current_symbols(&self) -> SymbolTableRef {
-        self.curr_symbols.clone().expect(&format!(
-            "No symbol table for class {}",
-            self.curr_class_name
-        ))
+        self.curr_symbols
+            .clone()
+            .unwrap_or_else(|| panic!("No symbol table for class {}", self.curr_class_name))
     }
 
     fn compile_class_var_declaration(&mut self, class_var_decl: &ClassVarDec) {
@@ -309,7 +445,7 @@ impl Compiler {
                 self.vm_write(format!("push constant {}", value));
             }
             Term::StringConstant(value) => {
-                self.vm_write(format!("push constant {}", value.len()));
+                self.vm_write(format!("push constant {}", value.chars().count()));
                 self.vm_write_str("call String.new 1");
                 for ch in value.chars() {
                     self.vm_write(format!("push constant {}", self.char_to_ascii(ch)));
@@ -328,14 +464,15 @@ impl Compiler {
                     self.vm_write_str("push pointer 0");
                 }
             },
-            Term::VarName(name) => {
-                self.compile_var_name(name);
+            Term::VarName { name, position } => {
+                self.compile_var_name(name, *position);
             }
             Term::VarNameWithIndex {
                 var_name,
+                position,
                 index_expression,
             } => {
-                self.compile_var_name_with_index(var_name, index_expression);
+                self.compile_var_name_with_index(var_name, *position, index_expression);
             }
             Term::ExpressionInParens(expr) => {
                 self.compile_expression(expr);
@@ -363,10 +500,10 @@ impl Compiler {
         match &call.class_or_instance_name {
             Some(name) => {
                 let symbols = self.get_current_symbols();
-                if let Some(_) = symbols.borrow().get_entry(&name) {
+                if symbols.borrow().get_entry(name).is_some() {
                     // It's an instance method call
                     is_method_call = true;
-                    self.compile_var_name(&name);
+                    self.compile_var_name(name, call.position);
                 }
             }
             None => {
@@ -388,15 +525,18 @@ impl Compiler {
                 } else {
                     // It's an instance method call
                     let symbols = self.get_current_symbols();
-                    let entry = symbols
-                        .borrow()
-                        .get_entry(name)
-                        .expect(&format!("Variable {} not found", name));
-                    match &entry.var_type {
-                        Type::Class(class_name) => {
+                    let entry = symbols.borrow().get_entry(name);
+                    match entry.map(|entry| entry.var_type) {
+                        Some(Type::Class(class_name)) => {
                             format!("{}.{}", class_name, call.subroutine_name)
                         }
-                        _ => panic!("Expected class type for instance method call"),
+                        _ => {
+                            self.error(
+                                call.position,
+                                format!("Cannot call method {} on {name}, which is not an object", call.subroutine_name),
+                            );
+                            return;
+                        }
                     }
                 }
             }
@@ -407,37 +547,54 @@ impl Compiler {
         self.vm_write(format!("call {} {}", full_name, num_args));
     }
 
-    fn compile_var_name(&mut self, name: &str) {
-        let (segment_str, index) = self.get_segment_and_index(name);
-        self.vm_write(format!("push {} {}", segment_str, index));
+    fn compile_var_name(&mut self, name: &str, position: SourcePosition) {
+        match self.get_segment_and_index(name, position) {
+            // The index of a constant is its value, which may be negative
+            Some((segment_str, index)) if segment_str == "constant" && (index as i16) < 0 => {
+                self.vm_write(format!("push constant {}", -(index as i16)));
+                self.vm_write_str("neg");
+            }
+            Some((segment_str, index)) => self.vm_write(format!("push {} {}", segment_str, index)),
+            None => {}
+        }
     }
 
-    fn get_segment_and_index(&mut self, name: &str) -> (String, u16) {
+    /// Looks up a variable, recording an error if it cannot be accessed here.
+    fn get_segment_and_index(&mut self, name: &str, position: SourcePosition) -> Option<(String, u16)> {
         let symbols = self.get_current_symbols();
-        let entry = symbols
-            .borrow()
-            .get_entry(name)
-            .expect(&format!("Variable {name} not found"));
+        let entry = symbols.borrow().get_entry(name);
+        let Some(entry) = entry else {
+            self.error(position, format!("Variable {name} not found"));
+            return None;
+        };
         let segment_str = match entry.segment {
             Segment::Static => "static".to_string(),
             Segment::This => match self.curr_subroutine_category {
                 Some(SubroutineCategory::Method) | Some(SubroutineCategory::Constructor) => {
                     "this".to_string()
                 }
-                _ => panic!("Cannot access field '{name}' in function"),
+                _ => {
+                    self.error(position, format!("Cannot access field '{name}' in function"));
+                    return None;
+                }
             },
             Segment::That => "that".to_string(),
             Segment::Argument => "argument".to_string(),
             Segment::Local => "local".to_string(),
             Segment::Pointer => "pointer".to_string(),
             Segment::Temp => "temp".to_string(),
-            Segment::Constant => panic!("Constant segment not valid for variable access"),
+            Segment::Constant => "constant".to_string(),
         };
-        (segment_str, entry.index)
+        Some((segment_str, entry.index))
     }
 
-    fn compile_var_name_with_index(&mut self, var_name: &str, index_expression: &Expression) {
-        self.compile_var_name(&var_name);
+    fn compile_var_name_with_index(
+        &mut self,
+        var_name: &str,
+        position: SourcePosition,
+        index_expression: &Expression,
+    ) {
+        self.compile_var_name(var_name, position);
         self.compile_expression(index_expression);
         self.vm_write_str("add");
         self.vm_write_str("pop pointer 1");
@@ -446,7 +603,8 @@ impl Compiler {
 
     fn initialize_char_map() -> HashMap<char, u8> {
         let mut map = HashMap::new();
-        for i in 0_u8..=127 {
+        // ASCII and the Hack key codes, from newline (128) to F12 (152)
+        for i in 0_u8..=152 {
             map.insert(i as char, i);
         }
         map
@@ -456,6 +614,15 @@ impl Compiler {
         *self.char_map.get(&ch).unwrap_or(&0)
     }
 
+    fn error(&mut self, position: SourcePosition, message: String) {
+        self.errors.push(CompileError {
+            class_name: self.curr_class_name.clone(),
+            subroutine_name: self.curr_subroutine_name.clone(),
+            position: Some(position),
+            message,
+        });
+    }
+
     fn vm_write_str(&mut self, line: &str) {
         self.vm_lines.push(line.to_string());
     }
@@ -468,7 +635,7 @@ impl Compiler {
 mod tests {
     use super::*;
     use crate::grammarous::StringCharStream;
-    use crate::jack::{Lexer, Parser};
+    use crate::jack::{LanguageLevel, Lexer, Parser, Precedence};
 
     #[test]
     fn test_compile_product_expression() {
@@ -544,7 +711,7 @@ mod tests {
         "#;
         let class = parse_class(code);
         let mut compiler = Compiler::new();
-        compiler.compile_class(&class);
+        compiler.compile_class(&class).unwrap();
 
         let vm_code = compiler.get_vm_code();
 
@@ -574,7 +741,7 @@ mod tests {
         "#;
         let class = parse_class(code);
         let mut compiler = Compiler::new();
-        compiler.compile_class(&class);
+        compiler.compile_class(&class).unwrap();
 
         let vm_code = compiler.get_vm_code();
 
@@ -615,7 +782,7 @@ mod tests {
         "#;
         let class = parse_class(code);
         let mut compiler = Compiler::new();
-        compiler.compile_class(&class);
+        compiler.compile_class(&class).unwrap();
 
         let vm_code = compiler.get_vm_code();
         
@@ -641,6 +808,259 @@ mod tests {
         assert!(vm_code.contains(&expected_code));
     }
 
+    #[test]
+    fn test_compile_errors() {
+        let code = "class Test {\n    field int count;\n\n    function void run(int n) {\n        let count = n;\n        let total = n;\n        do n.print();\n        return;\n    }\n}\n";
+        let class = parse_class(code);
+        let mut compiler = Compiler::new();
+
+        let errors: Vec<String> = compiler
+            .compile_class(&class)
+            .unwrap_err()
+            .iter()
+            .map(|error| error.to_string())
+            .collect();
+
+        assert_eq!(
+            errors,
+            vec![
+                "Test.run (line 5, column 13): Cannot access field 'count' in function",
+                "Test.run (line 6, column 13): Variable total not found",
+                "Test.run (line 7, column 12): Cannot call method print on n, which is not an object",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compile_for_statement() {
+        let code = r#"
+        class Test {
+            function void run(int n) {
+                var int i;
+                for (let i = 0; i < n; let i = i + 1) {
+                    if (i = 3) { continue; }
+                    if (i = 5) { break; }
+                    do Output.printInt(i);
+                }
+                return;
+            }
+        }
+        "#;
+        let class = parse_extended_class(code);
+        let mut compiler = Compiler::new();
+        compiler.compile_class(&class).unwrap();
+
+        let vm_code = compiler.get_vm_code();
+
+        let expected_code = vec![
+            "push constant 0",
+            "pop local 0",
+            "label FOR_1",
+            "push local 0",
+            "push argument 0",
+            "lt",
+            "not",
+            "if-goto FOR_3",
+            "push local 0",
+            "push constant 3",
+            "eq",
+            "not",
+            "if-goto IF_1",
+            "goto FOR_2",
+            "label IF_1",
+            "push local 0",
+            "push constant 5",
+            "eq",
+            "not",
+            "if-goto IF_2",
+            "goto FOR_3",
+            "label IF_2",
+            "push local 0",
+            "call Output.printInt 1",
+            "pop temp 0",
+            "label FOR_2",
+            "push local 0",
+            "push constant 1",
+            "add",
+            "pop local 0",
+            "goto FOR_1",
+            "label FOR_3",
+        ]
+        .join("\n");
+
+        assert!(vm_code.contains(&expected_code), "{vm_code}");
+    }
+
+    #[test]
+    fn test_compile_else_if_and_jumps_in_while() {
+        let code = r#"
+        class Test {
+            function int sign(int n) {
+                while (true) {
+                    if (n > 0) { return 1; } else if (n < 0) { return -1; } else { break; }
+                }
+                return 0;
+            }
+        }
+        "#;
+        let class = parse_extended_class(code);
+        let mut compiler = Compiler::new();
+        compiler.compile_class(&class).unwrap();
+
+        let vm_code = compiler.get_vm_code();
+
+        assert!(vm_code.contains("goto IF_2\nlabel IF_1\npush argument 0\npush constant 0\nlt\nnot\nif-goto IF_3"), "{vm_code}");
+        assert!(vm_code.contains("label IF_3\ngoto WHILE_2\nlabel IF_4"), "{vm_code}");
+    }
+
+    #[test]
+    fn test_compile_jumps_outside_of_loops() {
+        let code = "class Test {\n    function void run() {\n        break;\n        continue;\n        return;\n    }\n}\n";
+        let class = parse_extended_class(code);
+        let mut compiler = Compiler::new();
+
+        let errors: Vec<String> = compiler
+            .compile_class(&class)
+            .unwrap_err()
+            .iter()
+            .map(|error| error.to_string())
+            .collect();
+
+        assert_eq!(
+            errors,
+            vec![
+                "Test.run (line 3, column 9): break outside of a loop",
+                "Test.run (line 4, column 9): continue outside of a loop",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compile_with_standard_precedence() {
+        let code = r#"
+        class Test {
+            function boolean run(int a, int b) {
+                return a + 2 * b - 1 < 9 & ~(a = 3 - b);
+            }
+        }
+        "#;
+        let mut stream = StringCharStream::new(code);
+        let mut lexer = Lexer::new(&mut stream);
+        let mut parser = Parser::new(&mut lexer);
+        parser.set_precedence(Precedence::Standard);
+        let class = parser.create_class_ast().unwrap();
+        let mut compiler = Compiler::new();
+        compiler.compile_class(&class).unwrap();
+
+        let vm_code = compiler.get_vm_code();
+
+        let expected_code = vec![
+            "push argument 0",
+            "push constant 2",
+            "push argument 1",
+            "call Math.multiply 2",
+            "add",
+            "push constant 1",
+            "sub",
+            "push constant 9",
+            "lt",
+            "push argument 0",
+            "push constant 3",
+            "push argument 1",
+            "sub",
+            "eq",
+            "not",
+            "and",
+            "return",
+        ]
+        .join("\n");
+
+        assert!(vm_code.contains(&expected_code), "{vm_code}");
+    }
+
+    #[test]
+    fn test_compile_extended_literals() {
+        let code = r#"
+        class Test {
+            function void run() {
+                do Output.printString("\"\n");
+                do Output.printChar('A' + 0x0F);
+                return;
+            }
+        }
+        "#;
+        let class = parse_extended_class(code);
+        let mut compiler = Compiler::new();
+        compiler.compile_class(&class).unwrap();
+
+        let vm_code = compiler.get_vm_code();
+
+        let expected_code = [
+            "push constant 2",
+            "call String.new 1",
+            "push constant 34",
+            "call String.appendChar 2",
+            "push constant 128",
+            "call String.appendChar 2",
+            "call Output.printString 1",
+            "pop temp 0",
+            "push constant 65",
+            "push constant 15",
+            "add",
+        ]
+        .join("\n");
+
+        assert!(vm_code.contains(&expected_code), "{vm_code}");
+    }
+
+    #[test]
+    fn test_compile_constants() {
+        let code = r#"
+        class Test {
+            static int count;
+            const int MAX = 100;
+            const int MIN = -5;
+            const boolean DEBUG = true;
+            enum Direction { UP, DOWN }
+
+            function void run() {
+                let count = MAX + MIN;
+                if (DEBUG) { let count = Direction.DOWN + Test.MAX; }
+                let MAX = 1;
+                return;
+            }
+        }
+        "#;
+        let class = parse_extended_class(code);
+        let mut compiler = Compiler::new();
+
+        let errors: Vec<String> = compiler
+            .compile_class(&class)
+            .unwrap_err()
+            .iter()
+            .map(|error| error.to_string())
+            .collect();
+        assert_eq!(errors, vec!["Test.run (line 12, column 21): Cannot assign to constant MAX"]);
+
+        let expected_code = [
+            "push constant 100",
+            "push constant 5",
+            "neg",
+            "add",
+            "pop static 0",
+            "push constant 1",
+            "neg",
+            "not",
+            "if-goto IF_1",
+            "push constant 1",
+            "push constant 100",
+            "add",
+            "pop static 0",
+        ]
+        .join("\n");
+        let vm_code = compiler.get_vm_code();
+        assert!(vm_code.contains(&expected_code), "{vm_code}");
+    }
 
     fn parse_class(code: &str) -> Class {
         let mut stream = StringCharStream::new(code);
@@ -648,4 +1068,13 @@ mod tests {
         let mut parser = Parser::new(&mut lexer);
         parser.create_class_ast().unwrap()
     }
+
+    fn parse_extended_class(code: &str) -> Class {
+        let mut stream = StringCharStream::new(code);
+        let mut lexer = Lexer::new(&mut stream);
+        lexer.set_language_level(LanguageLevel::Extended);
+        let mut parser = Parser::new(&mut lexer);
+        parser.set_language_level(LanguageLevel::Extended);
+        parser.create_class_ast().unwrap()
+    }
 }
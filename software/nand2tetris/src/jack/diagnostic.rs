@@ -0,0 +1,163 @@
+use crate::jack::lexer::JackToken;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// Characters of one source line, counted from 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    /// Number of characters; 0 underlines the possibly qualified name at
+    /// `column`, like `Output.printInt`.
+    pub length: usize,
+}
+
+impl Span {
+    pub fn from_token(token: &JackToken) -> Self {
+        Self {
+            line: token.line,
+            column: token.column,
+            length: token.lexeme.chars().count(),
+        }
+    }
+}
+
+/// A message of any stage of the Jack compiler, from the lexer to the code
+/// generator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub file: Option<String>,
+    pub span: Option<Span>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            message: message.into(),
+            file: None,
+            span: None,
+            notes: vec![],
+        }
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(message)
+        }
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    pub fn with_file(mut self, file: &str) -> Self {
+        self.file = Some(file.to_string());
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    /// Formats the diagnostic like rustc does, quoting the offending line of
+    /// `source` with the span underlined.
+    pub fn render(&self, source: &str) -> String {
+        let mut output = format!("{}: {}\n", self.severity, self.message);
+        let source_line = self
+            .span
+            .and_then(|span| source.lines().nth(span.line.checked_sub(1)?).map(|line| (span, line)));
+        let gutter_width = self.span.map_or(1, |span| span.line.to_string().len());
+        let gutter = " ".repeat(gutter_width);
+
+        match (&self.file, self.span) {
+            (Some(file), Some(span)) => {
+                output += &format!("{gutter}--> {file}:{}:{}\n", span.line, span.column)
+            }
+            (Some(file), None) => output += &format!("{gutter}--> {file}\n"),
+            (None, Some(span)) => output += &format!("{gutter}--> {}:{}\n", span.line, span.column),
+            (None, None) => {}
+        }
+
+        if let Some((span, line)) = source_line {
+            let chars: Vec<char> = line.chars().collect();
+            let start = (span.column.max(1) - 1).min(chars.len());
+            // Keep tabs so that the carets line up with the quoted line
+            let indent: String = chars[..start]
+                .iter()
+                .map(|&ch| if ch == '\t' { '\t' } else { ' ' })
+                .collect();
+            let length = match span.length {
+                0 => chars[start..]
+                    .iter()
+                    .take_while(|ch| ch.is_ascii_alphanumeric() || **ch == '_' || **ch == '.')
+                    .count(),
+                length => length,
+            };
+            output += &format!("{gutter} |\n");
+            output += &format!("{} | {line}\n", span.line);
+            output += &format!("{gutter} | {indent}{}\n", "^".repeat(length.max(1)));
+        }
+
+        for note in &self.notes {
+            output += &format!("{gutter} = note: {note}\n");
+        }
+        output
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{file}:")?;
+        }
+        if let Some(span) = &self.span {
+            write!(f, "{}:{}:", span.line, span.column)?;
+        }
+        if self.file.is_some() || self.span.is_some() {
+            write!(f, " ")?;
+        }
+        write!(f, "{}: {}", self.severity, self.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_diagnostic() {
+        let source = "class Main {\n\tfunction void main() {\n\t\tdo Output.printStrin(1);\n";
+        let diagnostic = Diagnostic::error("unknown subroutine Output.printStrin")
+            .with_file("Main.jack")
+            .with_span(Span { line: 3, column: 6, length: 0 })
+            .with_note("in Main.main");
+
+        assert_eq!(
+            diagnostic.render(source),
+            "error: unknown subroutine Output.printStrin\n --> Main.jack:3:6\n  |\n3 | \t\tdo Output.printStrin(1);\n  | \t\t   ^^^^^^^^^^^^^^^^^\n  = note: in Main.main\n"
+        );
+        assert_eq!(diagnostic.to_string(), "Main.jack:3:6: error: unknown subroutine Output.printStrin");
+        assert_eq!(Diagnostic::warning("unused").render(source), "warning: unused\n");
+    }
+}
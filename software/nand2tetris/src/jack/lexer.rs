@@ -6,6 +6,23 @@ use std::collections::HashMap;
 pub type JackToken = Token<TokenType>;
 
 const MAX_JACK_INT_VALUE: u16 = 32767;
+/// The last key code of the Hack character set, F12.
+const MAX_HACK_CHAR_VALUE: u32 = 152;
+
+/// The Jack dialect a program is written in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum LanguageLevel {
+    /// Jack as specified by the nand2tetris course.
+    #[default]
+    Standard,
+    /// Adds `for` loops, `else if` chains, `break` and `continue`, class
+    /// constants and enums, character constants like `'A'`, hexadecimal and
+    /// binary integer constants like `0x7FFF` and `0b1010`, and escape
+    /// sequences in string and character constants: `\n` (the Hack newline
+    /// 128), `\b` (backspace 129), `\"`, `\'`, `\\` and `\xHH` for any Hack
+    /// character or key code.
+    Extended,
+}
 
 pub struct Lexer<'a> {
     stream: BufferedStream<'a, char>,
@@ -13,6 +30,7 @@ pub struct Lexer<'a> {
     column: usize,
     symbols: HashMap<char, TokenType>,
     keywords: HashMap<String, TokenType>,
+    language_level: LanguageLevel,
 }
 
 impl<'a> Lexer<'a> {
@@ -23,6 +41,26 @@ impl<'a> Lexer<'a> {
             column: 1,
             symbols: Self::init_symbols(),
             keywords: Self::init_keywords(),
+            language_level: LanguageLevel::Standard,
+        }
+    }
+
+    /// In extended Jack, `for`, `break`, `continue`, `const` and `enum` are
+    /// keywords instead of identifiers.
+    pub fn set_language_level(&mut self, level: LanguageLevel) {
+        self.language_level = level;
+        let extended_keywords = [
+            ("for", TokenType::For),
+            ("break", TokenType::Break),
+            ("continue", TokenType::Continue),
+            ("const", TokenType::Const),
+            ("enum", TokenType::Enum),
+        ];
+        for (keyword, token_type) in extended_keywords {
+            match level {
+                LanguageLevel::Standard => self.keywords.remove(keyword),
+                LanguageLevel::Extended => self.keywords.insert(keyword.to_string(), token_type),
+            };
         }
     }
 
@@ -134,8 +172,18 @@ impl<'a> Lexer<'a> {
         let mut lexeme = String::new();
         lexeme.push(first_digit);
 
+        let radix = match self.stream.peek() {
+            Some(prefix @ ('x' | 'b')) if first_digit == '0' && self.language_level == LanguageLevel::Extended => {
+                lexeme.push(prefix);
+                self.next_char();
+                if prefix == 'x' { 16 } else { 2 }
+            }
+            _ => 10,
+        };
+
         while let Some(ch) = self.stream.peek() {
-            if ch.is_ascii_digit() {
+            // Letters are taken as well so that `0b12` or `0x1G` are one invalid constant
+            if ch.is_ascii_digit() || (radix != 10 && ch.is_ascii_alphanumeric()) {
                 lexeme.push(ch);
                 self.next_char();
             } else {
@@ -143,23 +191,18 @@ impl<'a> Lexer<'a> {
             }
         }
 
-        match lexeme.parse::<u16>() {
+        let digits = if radix == 10 { &lexeme[..] } else { &lexeme[2..] };
+        match u16::from_str_radix(digits, radix) {
             Ok(value) => {
                 if value <= MAX_JACK_INT_VALUE {
                     JackToken::new(TokenType::IntegerConstant(value), lexeme, line, column)
                 } else {
-                    let message = format!(
-                        "Integer constant out of range: '{}' at line {}, column {}",
-                        lexeme, line, column
-                    );
+                    let message = format!("Integer constant out of range: '{lexeme}'");
                     JackToken::new(TokenType::Error { message }, lexeme, line, column)
                 }
             },
             Err(_) => {
-                let message = format!(
-                    "Invalid integer constant: '{}' at line {}, column {}",
-                    lexeme, line, column
-                );
+                let message = format!("Invalid integer constant: '{lexeme}'");
                 JackToken::new(TokenType::Error { message }, lexeme, line, column)
             }
         }
@@ -188,20 +231,101 @@ impl<'a> Lexer<'a> {
     fn string_constant(&mut self, line: usize, column: usize) -> JackToken {
         let mut lexeme = String::new();
         lexeme.push('"');
+        let mut value = String::new();
+        let mut error = None;
 
         while let Some(ch) = self.next_char() {
             lexeme.push(ch);
-            if ch == '"' {
-                // Closing quote found
-                return JackToken::new(TokenType::StringConstant(lexeme.clone()), lexeme, line, column);
+            match ch {
+                '"' => {
+                    // Closing quote found
+                    return match error {
+                        None => JackToken::new(TokenType::StringConstant(value), lexeme, line, column),
+                        Some(message) => JackToken::new(TokenType::Error { message }, lexeme, line, column),
+                    };
+                }
+                '\\' if self.language_level == LanguageLevel::Extended => {
+                    match self.escape_sequence(&mut lexeme) {
+                        Ok(ch) => value.push(ch),
+                        Err(message) => {
+                            error.get_or_insert(message);
+                        }
+                    }
+                }
+                _ => value.push(ch),
             }
         }
 
         // If we reach here, the string was not closed
-        let message = format!("Unterminated string constant at line {}, column {}", line, column);
+        let message = "Unterminated string constant".to_string();
         JackToken::new(TokenType::Error { message }, lexeme, line, column)
     }
 
+    /// Reads a character constant, whose value is the character code.
+    fn char_constant(&mut self, line: usize, column: usize) -> JackToken {
+        let mut lexeme = String::from("'");
+        let value = match self.stream.peek() {
+            None | Some('\'' | '\n') => Err("Empty character constant".to_string()),
+            Some('\\') => {
+                lexeme.push('\\');
+                self.next_char();
+                self.escape_sequence(&mut lexeme)
+            }
+            Some(ch) => {
+                lexeme.push(ch);
+                self.next_char();
+                match ch as u32 {
+                    32..=126 => Ok(ch),
+                    _ => Err(format!("Character not in the Hack character set: '{ch}'")),
+                }
+            }
+        };
+
+        if self.stream.peek() != Some('\'') {
+            let message = "Unterminated character constant".to_string();
+            return JackToken::new(TokenType::Error { message }, lexeme, line, column);
+        }
+        self.next_char();
+        lexeme.push('\'');
+
+        match value {
+            Ok(ch) => JackToken::new(TokenType::IntegerConstant(ch as u16), lexeme, line, column),
+            Err(message) => JackToken::new(TokenType::Error { message }, lexeme, line, column),
+        }
+    }
+
+    /// Reads the rest of an escape sequence after its backslash.
+    fn escape_sequence(&mut self, lexeme: &mut String) -> Result<char, String> {
+        let Some(ch) = self.next_char() else {
+            return Err("Unterminated escape sequence".to_string());
+        };
+        lexeme.push(ch);
+        match ch {
+            'n' => Ok('\u{80}'),
+            'b' => Ok('\u{81}'),
+            '"' | '\'' | '\\' => Ok(ch),
+            'x' => {
+                let mut digits = String::new();
+                while digits.len() < 2 {
+                    match self.stream.peek() {
+                        Some(digit) if digit.is_ascii_hexdigit() => {
+                            digits.push(digit);
+                            lexeme.push(digit);
+                            self.next_char();
+                        }
+                        _ => break,
+                    }
+                }
+                u32::from_str_radix(&digits, 16)
+                    .ok()
+                    .filter(|&code| digits.len() == 2 && code <= MAX_HACK_CHAR_VALUE)
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| format!("Invalid character code in escape sequence: '\\x{digits}'"))
+            }
+            _ => Err(format!("Unknown escape sequence: '\\{ch}'")),
+        }
+    }
+
     fn symbol_token(&mut self, ch: char, line: usize, column: usize) -> JackToken {
         if let Some(token_type) = self.symbols.get(&ch) {
             JackToken::new(token_type.clone(), ch.to_string(), line, column)
@@ -211,7 +335,7 @@ impl<'a> Lexer<'a> {
     }
 
     fn error_token_unexpected_char(ch: char, line: usize, column: usize) -> JackToken {
-        let message = format!("Unexpected character: '{ch}' at line {line}, column {column}");
+        let message = format!("Unexpected character: '{ch}'");
         JackToken::new(TokenType::Error { message }, ch.to_string(), line, column)
     }
 }
@@ -250,6 +374,9 @@ impl Stream<JackToken> for Lexer<'_> {
                 Some('"') => {
                     return Some(self.string_constant(line, column));
                 }
+                Some('\'') if self.language_level == LanguageLevel::Extended => {
+                    return Some(self.char_constant(line, column));
+                }
                 Some(ch) => {
                     return Some(Self::symbol_token(self, ch, line, column));
                 }
@@ -307,7 +434,7 @@ class Main {
             Token::new(TokenType::Dot, ".".to_string(), 7, 15),
             Token::new(TokenType::Identifier("println".to_string()), "println".to_string(), 7, 16),
             Token::new(TokenType::LParen, "(".to_string(), 7, 23),
-            Token::new(TokenType::StringConstant("\"Hello, World!\"".to_string()), "\"Hello, World!\"".to_string(), 7, 24),
+            Token::new(TokenType::StringConstant("Hello, World!".to_string()), "\"Hello, World!\"".to_string(), 7, 24),
             Token::new(TokenType::RParen, ")".to_string(), 7, 39),
             Token::new(TokenType::Semicolon, ";".to_string(), 7, 40),
             Token::new(TokenType::RBrace, "}".to_string(), 8, 5),
@@ -322,4 +449,58 @@ class Main {
 
     }
 
+    fn token_types(input: &str, level: LanguageLevel) -> Vec<TokenType> {
+        let mut char_stream = StringCharStream::new(input);
+        let mut lexer = Lexer::new(&mut char_stream);
+        lexer.set_language_level(level);
+        std::iter::from_fn(|| lexer.advance()).map(|token| token.token_type).collect()
+    }
+
+    #[test]
+    fn test_extended_literals() {
+        let input = r#"'A' '\n' '\'' 0x7FFF 0b1010 0 "say \"hi\"\n" "C:\\x" "\x84\\""#;
+
+        assert_eq!(
+            token_types(input, LanguageLevel::Extended),
+            vec![
+                TokenType::IntegerConstant(65),
+                TokenType::IntegerConstant(128),
+                TokenType::IntegerConstant(39),
+                TokenType::IntegerConstant(32767),
+                TokenType::IntegerConstant(10),
+                TokenType::IntegerConstant(0),
+                TokenType::StringConstant("say \"hi\"\u{80}".to_string()),
+                TokenType::StringConstant("C:\\x".to_string()),
+                TokenType::StringConstant("\u{84}\\".to_string()),
+            ]
+        );
+
+        // Standard Jack keeps backslashes and has no other literal forms
+        assert_eq!(
+            token_types(r#"0x1F "a\nb""#, LanguageLevel::Standard),
+            vec![
+                TokenType::IntegerConstant(0),
+                TokenType::Identifier("x1F".to_string()),
+                TokenType::StringConstant("a\\nb".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extended_literal_errors() {
+        let error = |input: &str| match token_types(input, LanguageLevel::Extended).remove(0) {
+            TokenType::Error { message } => message,
+            token_type => panic!("Expected an error, got {token_type:?}"),
+        };
+
+        assert_eq!(error("0x8000"), "Integer constant out of range: '0x8000'");
+        assert_eq!(error("0b102"), "Invalid integer constant: '0b102'");
+        assert_eq!(error("0x"), "Invalid integer constant: '0x'");
+        assert_eq!(error("''"), "Empty character constant");
+        assert_eq!(error("'ab'"), "Unterminated character constant");
+        assert_eq!(error("'é'"), "Character not in the Hack character set: 'é'");
+        assert_eq!(error(r#""a\qb""#), "Unknown escape sequence: '\\q'");
+        assert_eq!(error(r#""\x99""#), "Invalid character code in escape sequence: '\\x99'");
+        assert_eq!(error(r#""abc\""#), "Unterminated string constant");
+    }
 }
\ No newline at end of file
@@ -1,15 +1,25 @@
 use crate::jack::ast::*;
+use crate::jack::diagnostic::Diagnostic;
 use crate::jack::lexer::JackToken;
 use crate::jack::parse_tree::{ParseTreeNode, ParseTreeNodeData};
-use crate::jack::token_type::TokenTypeCategory;
+use crate::jack::token_type::{TokenType, TokenTypeCategory};
 
-pub fn convert_class(class_node: &ParseTreeNode) -> Result<Class, String> {
+/// Converts the parse tree of a class into its AST.
+///
+/// Trees built by `Parser` always convert; errors are for malformed trees.
+pub fn convert_class(class_node: &ParseTreeNode) -> Result<Class, Diagnostic> {
+    convert_class_node(class_node).map_err(Diagnostic::error)
+}
+
+fn convert_class_node(class_node: &ParseTreeNode) -> Result<Class, String> {
     let class_node = match class_node {
         ParseTreeNode::NonTerminal(node) if node.name == "class" => node,
         _ => return Err("Expected class parse tree node".to_string()),
     };
     let name;
     let mut class_var_declarations = vec![];
+    let mut constant_declarations = vec![];
+    let mut enum_declarations = vec![];
     let mut subroutine_declarations = vec![];
 
     if let Some(ParseTreeNode::Terminal(token)) = class_node.children.get(1) {
@@ -24,6 +34,17 @@ pub fn convert_class(class_node: &ParseTreeNode) -> Result<Class, String> {
                 let class_var_dec = convert_class_var_declaration(node)?;
                 class_var_declarations.push(class_var_dec);
             }
+            ParseTreeNode::NonTerminal(node) if node.name == "classConstDec" => {
+                constant_declarations.push(convert_constant_declaration(node)?);
+            }
+            ParseTreeNode::NonTerminal(node) if node.name == "enumDec" => {
+                let name_node = get_terminal_at(&node.children, 1, TokenTypeCategory::Identifier)?;
+                enum_declarations.push(EnumDec {
+                    name: name_node.lexeme.clone(),
+                    members: get_identifiers(&node.children[2..]),
+                    position: token_position(name_node),
+                });
+            }
             ParseTreeNode::NonTerminal(node) if node.name == "subroutineDec" => {
                 let subroutine_dec = convert_subroutine_declaration(node)?;
                 subroutine_declarations.push(subroutine_dec);
@@ -35,16 +56,50 @@ pub fn convert_class(class_node: &ParseTreeNode) -> Result<Class, String> {
     Ok(Class {
         name,
         class_var_declarations,
+        constant_declarations,
+        enum_declarations,
         subroutine_declarations,
     })
 }
 
+fn convert_constant_declaration(constant_dec_node: &ParseTreeNodeData) -> Result<ConstantDec, String> {
+    let children = &constant_dec_node.children;
+
+    let type_node = children.get(1).ok_or("Missing type node")?;
+    let var_type = convert_type_node(type_node)?;
+    let position = node_position(type_node)?;
+    let name = get_terminal_at(children, 2, TokenTypeCategory::Identifier)?.lexeme.clone();
+
+    // The parser only accepts literal values, possibly negated
+    let value_node = get_non_terminal_at(children, 4, "term")?;
+    let value = match convert_term(value_node)? {
+        Term::IntegerConstant(value) => value as i16,
+        Term::UnaryOp {
+            operator: UnaryOperator::Negate,
+            term,
+        } => match *term {
+            Term::IntegerConstant(value) => -(value as i16),
+            _ => return Err(format!("Invalid value of constant {name}")),
+        },
+        Term::KeywordConstant(KeywordConstant::True) => -1,
+        Term::KeywordConstant(KeywordConstant::False | KeywordConstant::Null) => 0,
+        _ => return Err(format!("Invalid value of constant {name}")),
+    };
+
+    Ok(ConstantDec {
+        name,
+        var_type,
+        value,
+        position,
+    })
+}
+
 fn convert_class_var_declaration(
     class_var_dec_node: &ParseTreeNodeData,
 ) -> Result<ClassVarDec, String> {
     let children = &class_var_dec_node.children;
 
-    let catg_node = children.get(0).ok_or("Missing category node")?;
+    let catg_node = children.first().ok_or("Missing category node")?;
     let category = match catg_node {
         ParseTreeNode::Terminal(token) if token.lexeme == "static" => ClassVarCategory::Static,
         ParseTreeNode::Terminal(token) if token.lexeme == "field" => ClassVarCategory::Field,
@@ -59,6 +114,7 @@ fn convert_class_var_declaration(
         category,
         var_type,
         names,
+        position: node_position(type_node)?,
     })
 }
 
@@ -79,7 +135,7 @@ fn convert_subroutine_declaration(
 ) -> Result<SubroutineDec, String> {
     let children = &subroutine_dec_node.children;
 
-    let category = match children.get(0) {
+    let category = match children.first() {
         Some(ParseTreeNode::Terminal(token)) if token.lexeme == "function" => {
             SubroutineCategory::Function
         }
@@ -97,6 +153,7 @@ fn convert_subroutine_declaration(
         Some(type_node) => Some(convert_type_node(type_node)?),
         _ => return Err("Invalid return type node".to_string()),
     };
+    let position = node_position(&children[1])?;
 
     let name = if let Some(ParseTreeNode::Terminal(token)) = children.get(2) {
         token.lexeme.clone()
@@ -126,6 +183,7 @@ fn convert_subroutine_declaration(
     Ok(SubroutineDec {
         category,
         return_type,
+        position,
         name,
         parameters,
         body,
@@ -179,6 +237,17 @@ fn convert_statements(statements_node: &ParseTreeNodeData) -> Result<Vec<Stateme
                 "returnStatement" => {
                     statements.push(convert_return_statement(node)?);
                 }
+                "forStatement" => {
+                    statements.push(convert_for_statement(node)?);
+                }
+                "breakStatement" => {
+                    let token = get_terminal_at(&node.children, 0, TokenTypeCategory::Break)?;
+                    statements.push(Statement::Break { position: token_position(token) });
+                }
+                "continueStatement" => {
+                    let token = get_terminal_at(&node.children, 0, TokenTypeCategory::Continue)?;
+                    statements.push(Statement::Continue { position: token_position(token) });
+                }
                 _ => {}
             }
         }
@@ -208,6 +277,53 @@ fn convert_while_statement(while_stmt_node: &ParseTreeNodeData) -> Result<Statem
     })
 }
 
+fn convert_for_statement(for_stmt_node: &ParseTreeNodeData) -> Result<Statement, String> {
+    // for ( initialization ; condition ; step ) { statements }
+    // Every part of the header is optional; a given initialization includes its ';'
+    let children = &for_stmt_node.children;
+    let mut idx = 2;
+
+    let initialization = match children.get(idx) {
+        Some(ParseTreeNode::NonTerminal(node)) => Some(Box::new(convert_simple_statement(node)?)),
+        _ => None,
+    };
+    idx += 1;
+
+    let condition = match get_non_terminal_at(children, idx, "expression") {
+        Ok(node) => {
+            idx += 1;
+            Some(convert_expression(node)?)
+        }
+        Err(_) => None,
+    };
+    get_terminal_at(children, idx, TokenTypeCategory::Semicolon)?;
+    idx += 1;
+
+    let step = match children.get(idx) {
+        Some(ParseTreeNode::NonTerminal(node)) => Some(Box::new(convert_simple_statement(node)?)),
+        _ => None,
+    };
+
+    let statements_node = get_non_terminal_at(children, children.len() - 2, "statements")?;
+    let body_statements = convert_statements(statements_node)?;
+
+    Ok(Statement::For {
+        initialization,
+        condition,
+        step,
+        body_statements,
+    })
+}
+
+/// Converts the let or do statement of a for loop header.
+fn convert_simple_statement(node: &ParseTreeNodeData) -> Result<Statement, String> {
+    match node.name.as_str() {
+        "letStatement" => convert_let_statement(node),
+        "doStatement" => convert_do_statement(node),
+        _ => Err("Invalid statement in for loop".to_string()),
+    }
+}
+
 fn convert_return_statement(return_stmt_node: &ParseTreeNodeData) -> Result<Statement, String> {
     let children = &return_stmt_node.children;
 
@@ -235,7 +351,10 @@ fn convert_if_statement(if_stmt_node: &ParseTreeNodeData) -> Result<Statement, S
     let if_statements = convert_statements(statements_node)?;
     let mut else_statements = None;
 
-    if children.len() > 7 {
+    if let Ok(else_if_node) = get_non_terminal_at(children, 8, "ifStatement") {
+        // else if, in extended Jack
+        else_statements = Some(vec![convert_if_statement(else_if_node)?]);
+    } else if children.len() > 7 {
         let else_statements_node = get_non_terminal_at(children, 9, "statements")?;
         else_statements = Some(convert_statements(else_statements_node)?);
     }
@@ -255,10 +374,10 @@ fn get_non_terminal_at<'a>(
     let node = nodes
         .get(idx)
         .ok_or("Child index out of bounds".to_string())?;
-    if let ParseTreeNode::NonTerminal(n) = node {
-        if n.name == name {
-            return Ok(n);
-        }
+    if let ParseTreeNode::NonTerminal(n) = node
+        && n.name == name
+    {
+        return Ok(n);
     }
     Err("No non-terminal found".to_string())
 }
@@ -271,10 +390,10 @@ fn get_terminal_at(
     let node = nodes
         .get(idx)
         .ok_or("Child index out of bounds".to_string())?;
-    if let ParseTreeNode::Terminal(token) = node {
-        if token.token_type.get_category() == token_type_category {
-            return Ok(token);
-        }
+    if let ParseTreeNode::Terminal(token) = node
+        && token.token_type.get_category() == token_type_category
+    {
+        return Ok(token);
     }
     Err("No terminal found".to_string())
 }
@@ -282,8 +401,8 @@ fn get_terminal_at(
 fn convert_let_statement(let_stmt_node: &ParseTreeNodeData) -> Result<Statement, String> {
     let children = &let_stmt_node.children;
 
-    let var_name = if let Some(ParseTreeNode::Terminal(token)) = children.get(1) {
-        token.lexeme.clone()
+    let (var_name, position) = if let Some(ParseTreeNode::Terminal(token)) = children.get(1) {
+        (token.lexeme.clone(), token_position(token))
     } else {
         return Err("Invalid variable name node in let statement".to_string());
     };
@@ -308,7 +427,14 @@ fn convert_let_statement(let_stmt_node: &ParseTreeNodeData) -> Result<Statement,
         _ => None,
     };
 
-    let value_expression = if let Some(expr_node) = children.get(children.len() - 2) {
+    // The last child is the semicolon, except in the step of a for loop
+    let value_index = match children.last() {
+        Some(ParseTreeNode::Terminal(token)) if token.token_type.get_category() == TokenTypeCategory::Semicolon => {
+            children.len() - 2
+        }
+        _ => children.len() - 1,
+    };
+    let value_expression = if let Some(expr_node) = children.get(value_index) {
         match expr_node {
             ParseTreeNode::NonTerminal(node) if node.name == "expression" => {
                 convert_expression(node)?
@@ -321,6 +447,7 @@ fn convert_let_statement(let_stmt_node: &ParseTreeNodeData) -> Result<Statement,
 
     Ok(Statement::Let {
         var_name,
+        position,
         index_expression,
         value_expression,
     })
@@ -369,21 +496,19 @@ fn convert_expression(expression_node: &ParseTreeNodeData) -> Result<Expression,
 
 fn convert_term(term_node: &ParseTreeNodeData) -> Result<Term, String> {
     let children = &term_node.children;
-    let first_child = children.get(0).ok_or("Empty term node")?;
+    let first_child = children.first().ok_or("Empty term node")?;
 
     match first_child {
         ParseTreeNode::Terminal(token) => match token.token_type.get_category() {
-            TokenTypeCategory::IntegerConstant => {
-                let value = token
-                    .lexeme
-                    .parse::<u16>()
-                    .map_err(|_| "Invalid integer constant".to_string())?;
-                Ok(Term::IntegerConstant(value))
-            }
-            TokenTypeCategory::StringConstant => {
-                let value = token.lexeme.trim_matches('"').to_string();
-                Ok(Term::StringConstant(value))
-            }
+            // The lexer has decoded the literal forms and escape sequences
+            TokenTypeCategory::IntegerConstant => match token.token_type {
+                TokenType::IntegerConstant(value) => Ok(Term::IntegerConstant(value)),
+                _ => Err("Invalid integer constant".to_string()),
+            },
+            TokenTypeCategory::StringConstant => match &token.token_type {
+                TokenType::StringConstant(value) => Ok(Term::StringConstant(value.clone())),
+                _ => Err("Invalid string constant".to_string()),
+            },
             TokenTypeCategory::True => Ok(Term::KeywordConstant(KeywordConstant::True)),
             TokenTypeCategory::False => Ok(Term::KeywordConstant(KeywordConstant::False)),
             TokenTypeCategory::Null => Ok(Term::KeywordConstant(KeywordConstant::Null)),
@@ -393,6 +518,10 @@ fn convert_term(term_node: &ParseTreeNodeData) -> Result<Term, String> {
             TokenTypeCategory::Minus | TokenTypeCategory::Tilde => convert_unary_operation(term_node),
             _ => Err("Invalid term node".to_string()),
         },
+        // Operands grouped by the parser with standard precedence
+        ParseTreeNode::NonTerminal(node) if node.name == "expression" => {
+            Ok(Term::ExpressionInParens(Box::new(convert_expression(node)?)))
+        }
         _ => Err("Invalid term node".to_string()),
     }
 }
@@ -400,7 +529,9 @@ fn convert_term(term_node: &ParseTreeNodeData) -> Result<Term, String> {
 fn convert_unary_operation(term_node: &ParseTreeNodeData) -> Result<Term, String> {
     let children = &term_node.children;
 
-    let operator_node = children.get(0).ok_or("Missing operator in unary operation")?;
+    let operator_node = children
+        .first()
+        .ok_or("Missing operator in unary operation")?;
     let operator = match operator_node {
         ParseTreeNode::Terminal(token) => match token.lexeme.as_str() {
             "-" => UnaryOperator::Negate,
@@ -419,7 +550,7 @@ fn convert_unary_operation(term_node: &ParseTreeNodeData) -> Result<Term, String
     })
 }
 
-fn convert_grouped_expression(nodes: &Vec<ParseTreeNode>) -> Result<Term, String> {
+fn convert_grouped_expression(nodes: &[ParseTreeNode]) -> Result<Term, String> {
     if nodes.len() != 3 {
         return Err("Invalid grouped expression structure".to_string());
     }
@@ -433,24 +564,40 @@ fn convert_grouped_expression(nodes: &Vec<ParseTreeNode>) -> Result<Term, String
     Ok(Term::ExpressionInParens(Box::new(expression)))
 }
 
-fn convert_expression_w_identifier(nodes: &Vec<ParseTreeNode>) -> Result<Term, String> {
-    let first_ident = if let ParseTreeNode::Terminal(token) = &nodes[0] {
-        Term::VarName(token.lexeme.clone())
-    } else {
+fn convert_expression_w_identifier(nodes: &[ParseTreeNode]) -> Result<Term, String> {
+    let ParseTreeNode::Terminal(first_token) = &nodes[0] else {
         return Err("Invalid identifier term".to_string());
     };
+    let position = token_position(first_token);
 
     if nodes.len() == 1 {
-        return Ok(first_ident);
+        return Ok(Term::VarName {
+            name: first_token.lexeme.clone(),
+            position,
+        });
     }
+    let is_call = nodes.iter().any(|node| {
+        matches!(node, ParseTreeNode::Terminal(token) if token.token_type.get_category() == TokenTypeCategory::LParen)
+    });
 
     let second_node = &nodes[1];
     match second_node {
         ParseTreeNode::Terminal(token) => match token.token_type.get_category() {
-           TokenTypeCategory::Dot | TokenTypeCategory::LParen => {
+           TokenTypeCategory::Dot | TokenTypeCategory::LParen if is_call => {
                 let subroutine_call = convert_subroutine_call(nodes)?;
                 Ok(Term::SubroutineCall(subroutine_call))
             }
+            // A qualified constant like `Direction.UP`, looked up by its full name
+            TokenTypeCategory::Dot => {
+                let name = nodes
+                    .iter()
+                    .map(|node| match node {
+                        ParseTreeNode::Terminal(token) => Ok(token.lexeme.as_str()),
+                        _ => Err("Invalid qualified name".to_string()),
+                    })
+                    .collect::<Result<String, String>>()?;
+                Ok(Term::VarName { name, position })
+            }
             TokenTypeCategory::LBracket => {
                 convert_array_access(nodes)
             }
@@ -466,8 +613,8 @@ fn convert_array_access(
     // nodes
     // 0          1   2           3
     // identifier '[' expression ']'
-    let var_name = if let ParseTreeNode::Terminal(token) = &nodes[0] {
-        token.lexeme.clone()
+    let (var_name, position) = if let ParseTreeNode::Terminal(token) = &nodes[0] {
+        (token.lexeme.clone(), token_position(token))
     } else {
         return Err("Invalid identifier in array access".to_string());
     };
@@ -476,6 +623,7 @@ fn convert_array_access(
 
     Ok(Term::VarNameWithIndex {
         var_name,
+        position,
         index_expression: Box::new(index_expression),
     })
 }
@@ -511,6 +659,7 @@ fn convert_subroutine_call(
 
     Ok(SubroutineCall {
         class_or_instance_name,
+        position: token_position(first),
         subroutine_name,
         arguments,
     })
@@ -540,16 +689,34 @@ fn convert_var_declaration(var_dec_node: &ParseTreeNodeData) -> Result<VarDec, S
     let var_type = convert_type_node(type_node)?;
     let names = get_identifiers(&children[2..]);
 
-    Ok(VarDec { var_type, names })
+    Ok(VarDec {
+        var_type,
+        names,
+        position: node_position(type_node)?,
+    })
+}
+
+fn token_position(token: &JackToken) -> SourcePosition {
+    SourcePosition {
+        line: token.line,
+        column: token.column,
+    }
+}
+
+fn node_position(node: &ParseTreeNode) -> Result<SourcePosition, String> {
+    match node {
+        ParseTreeNode::Terminal(token) => Ok(token_position(token)),
+        _ => Err("Expected a terminal node".to_string()),
+    }
 }
 
 fn get_identifiers(nodes: &[ParseTreeNode]) -> Vec<String> {
     let mut identifiers = vec![];
     for node in nodes {
-        if let ParseTreeNode::Terminal(token) = node {
-            if token.token_type.get_category() == TokenTypeCategory::Identifier {
-                identifiers.push(token.lexeme.clone());
-            }
+        if let ParseTreeNode::Terminal(token) = node
+            && token.token_type.get_category() == TokenTypeCategory::Identifier
+        {
+            identifiers.push(token.lexeme.clone());
         }
     }
 
@@ -558,7 +725,7 @@ fn get_identifiers(nodes: &[ParseTreeNode]) -> Vec<String> {
 
 fn convert_parameter_list(
     param_list_node: &ParseTreeNodeData,
-) -> Result<Vec<(Type, String)>, String> {
+) -> Result<Vec<(Type, String, SourcePosition)>, String> {
     let mut parameters = vec![];
     let children = &param_list_node.children;
     let mut i = 0;
@@ -567,7 +734,7 @@ fn convert_parameter_list(
             let param_type = convert_type_node(type_node)?;
             if let Some(ParseTreeNode::Terminal(token)) = children.get(i + 1) {
                 let param_name = token.lexeme.clone();
-                parameters.push((param_type, param_name));
+                parameters.push((param_type, param_name, node_position(type_node)?));
                 i += 3; // Move to the next type
             } else {
                 return Err("Invalid parameter name node".to_string());
@@ -769,8 +936,8 @@ mod tests {
 
         let parse_tree = parser
             .parse_class()
-            .map_err(|e| format!("Parsing error: {}", e))?;
+            .map_err(|e| format!("Parsing error: {:?}", e))?;
         parse_tree_printer::print_parse_tree(&parse_tree);
-        convert_class(&parse_tree)
+        convert_class(&parse_tree).map_err(|e| e.to_string())
     }
 }
@@ -84,6 +84,11 @@ impl <'a> ParseTreePrinter<'a> {
             Else => "keyword",
             While => "keyword",
             Return => "keyword",
+            For => "keyword",
+            Break => "keyword",
+            Continue => "keyword",
+            Const => "keyword",
+            Enum => "keyword",
             LBrace => "symbol",
             RBrace => "symbol",
             LParen => "symbol",
@@ -111,14 +116,11 @@ impl <'a> ParseTreePrinter<'a> {
     }
 
     fn print_text(&mut self, text: &str) {
-        match &mut self.output {
-            Some(output) => {
-                for _ in 0..self.indent {
-                    output.print("  ");
-                }
-                output.println(text);
-            },
-            None => (),
+        if let Some(output) = &mut self.output {
+            for _ in 0..self.indent {
+                output.print("  ");
+            }
+            output.println(text);
         }
     }
 }
@@ -148,6 +150,12 @@ pub struct StringOutput {
     content: String,
 }
 
+impl Default for StringOutput {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl StringOutput {
     pub fn new() -> Self {
         StringOutput {
@@ -1,32 +1,93 @@
 use crate::grammarous::stream::{BufferedStream, Stream};
 use crate::jack::ast;
-use crate::jack::lexer::JackToken;
+use crate::jack::diagnostic::{Diagnostic, Span};
+use crate::jack::lexer::{JackToken, LanguageLevel};
 use crate::jack::parse_tree::ParseTreeAction::{
     CheckName, CheckTokenTypeCategory, NavigateToChildByIndex,
 };
 use crate::jack::parse_tree::{ParseTreeNode, ParseTreeNodeData};
 use crate::jack::parse_tree_converter::convert_class;
+use crate::jack::token_type::TokenType;
 use crate::jack::token_type::TokenTypeCategory::{self, *};
 
+/// How binary operators without parentheses are grouped.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Precedence {
+    /// All operators are applied from left to right, as specified by the
+    /// nand2tetris course: `1 + 2 * 3` is 9.
+    #[default]
+    LeftToRight,
+    /// `*` and `/` bind tighter than `+` and `-`, which bind tighter than the
+    /// comparisons, which bind tighter than `&` and `|`: `1 + 2 * 3` is 7.
+    Standard,
+}
+
 pub struct Parser<'a> {
     stream: BufferedStream<'a, JackToken>,
+    /// Syntax errors the parser recovered from.
+    errors: Vec<Diagnostic>,
+    warnings: Vec<Diagnostic>,
+    language_level: LanguageLevel,
+    precedence: Precedence,
 }
 
 impl<'a> Parser<'a> {
     pub fn new(stream: &'a mut dyn Stream<JackToken>) -> Self {
         Self {
             stream: BufferedStream::new(stream),
+            errors: Vec::new(),
+            warnings: Vec::new(),
+            language_level: LanguageLevel::Standard,
+            precedence: Precedence::LeftToRight,
         }
     }
 
-    pub fn create_class_ast(&mut self) -> Result<ast::Class, String> {
+    /// Extended Jack also needs a lexer with the same language level.
+    pub fn set_language_level(&mut self, level: LanguageLevel) {
+        self.language_level = level;
+    }
+
+    /// With standard precedence, the operands of operators that bind tighter
+    /// are grouped into terms that hold an expression without parentheses.
+    pub fn set_precedence(&mut self, precedence: Precedence) {
+        self.precedence = precedence;
+    }
+
+    /// Returns the warnings collected so far, like expressions whose value
+    /// depends on the precedence.
+    pub fn take_warnings(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    pub fn create_class_ast(&mut self) -> Result<ast::Class, Vec<Diagnostic>> {
         let parse_tree = self.parse_class()?;
-        convert_class(&parse_tree)
+        convert_class(&parse_tree).map_err(|e| vec![e])
     }
 
-    pub fn parse_class(&mut self) -> Result<ParseTreeNode, String> {
+    /// Parses a class and returns all syntax errors in it.
+    pub fn parse_class(&mut self) -> Result<ParseTreeNode, Vec<Diagnostic>> {
+        let (parse_tree, errors) = self.parse_class_with_errors();
+        if errors.is_empty() {
+            Ok(parse_tree)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Parses a class, recovering from syntax errors.
+    ///
+    /// After an error the parser skips to the next `;`, `}` or keyword that
+    /// starts a statement or declaration and continues from there. The
+    /// returned parse tree lacks the parts that could not be parsed.
+    pub fn parse_class_with_errors(&mut self) -> (ParseTreeNode, Vec<Diagnostic>) {
         let mut class_data = ParseTreeNodeData::new("class", None);
+        if let Err(error) = self.parse_class_into(&mut class_data) {
+            self.errors.push(error);
+        }
+        (ParseTreeNode::NonTerminal(class_data), std::mem::take(&mut self.errors))
+    }
 
+    fn parse_class_into(&mut self, class_data: &mut ParseTreeNodeData) -> Result<(), Diagnostic> {
         let mut token = self.consume(Class)?;
         class_data.add_token(token);
 
@@ -36,65 +97,87 @@ impl<'a> Parser<'a> {
         token = self.consume(LBrace)?;
         class_data.add_token(token);
 
-        self.parse_class_var_declarations(&mut class_data)?;
-        self.parse_subroutine_declarations(&mut class_data)?;
+        self.parse_class_var_declarations(class_data)?;
+        self.parse_subroutine_declarations(class_data)?;
 
+        // A missing brace at the end was already reported with the error that
+        // made the parser skip to the end
+        if self.peek().is_none() && !self.errors.is_empty() {
+            return Ok(());
+        }
         token = self.consume(RBrace)?;
         class_data.add_token(token);
 
-        Ok(ParseTreeNode::NonTerminal(class_data))
+        Ok(())
     }
 
     fn parse_subroutine_declarations(
         &mut self,
         class_data: &mut ParseTreeNodeData,
-    ) -> Result<(), String> {
+    ) -> Result<(), Diagnostic> {
         loop {
             let next_token = self.peek();
             if next_token.is_none() {
-                return Err(
-                    "Unexpected end of input while parsing subroutine declarations".to_string(),
-                );
+                if !self.errors.is_empty() {
+                    return Ok(());
+                }
+                return Err(Diagnostic::error("Unexpected end of input while parsing subroutine declarations"));
             }
             let next_token = next_token.unwrap();
             match next_token.token_type.get_category() {
-                Constructor | Function | Method => {
-                    let mut subroutine_dec = ParseTreeNodeData::new("subroutineDec", None);
+                Constructor | Function | Method => match self.parse_subroutine_declaration() {
+                    Ok(subroutine_dec) => class_data.add_child(subroutine_dec),
+                    Err(error) => {
+                        self.errors.push(error);
+                        self.skip_to_subroutine_declaration();
+                    }
+                },
+                RBrace => break,
+                _ => {
+                    let token = self.consume_any()?;
+                    self.errors.push(Self::unexpected_token(
+                        &token,
+                        format!("Expected subroutine declaration, found {:?}", token.token_type),
+                    ));
+                    self.skip_to_subroutine_declaration();
+                }
+            }
+        }
 
-                    let token = self.consume_any_of(&[Constructor, Function, Method])?;
-                    let subroutine_catg = token.token_type.get_category();
-                    subroutine_dec.add_token(token);
+        Ok(())
+    }
 
-                    let token = self.consume_type(true)?;
-                    subroutine_dec.add_token(token);
+    fn parse_subroutine_declaration(&mut self) -> Result<ParseTreeNodeData, Diagnostic> {
+        let mut subroutine_dec = ParseTreeNodeData::new("subroutineDec", None);
 
-                    let token = self.consume(Identifier)?;
-                    subroutine_dec.add_token(token);
+        let token = self.consume_any_of(&[Constructor, Function, Method])?;
+        let subroutine_catg = token.token_type.get_category();
+        subroutine_dec.add_token(token);
 
-                    let token = self.consume(LParen)?;
-                    subroutine_dec.add_token(token);
+        let token = self.consume_type(true)?;
+        subroutine_dec.add_token(token);
 
-                    self.parse_parameter_list(&mut subroutine_dec)?;
+        let token = self.consume(Identifier)?;
+        subroutine_dec.add_token(token);
 
-                    let token = self.consume(RParen)?;
-                    subroutine_dec.add_token(token);
+        let token = self.consume(LParen)?;
+        subroutine_dec.add_token(token);
 
-                    self.parse_subroutine_body(&mut subroutine_dec, Some(subroutine_catg))?;
+        self.parse_parameter_list(&mut subroutine_dec)?;
 
-                    class_data.add_child(subroutine_dec);
-                }
-                _ => break,
-            }
-        }
+        let token = self.consume(RParen)?;
+        subroutine_dec.add_token(token);
 
-        Ok(())
+        self.parse_subroutine_body(&mut subroutine_dec, Some(subroutine_catg))?;
+
+        Ok(subroutine_dec)
     }
 
     fn parse_subroutine_body(
         &mut self,
         subroutine_dec: &mut ParseTreeNodeData,
         subroutine_catg: Option<TokenTypeCategory>,
-    ) -> Result<(), String> {
+    ) -> Result<(), Diagnostic> {
         let mut subroutine_body = ParseTreeNodeData::new("subroutineBody", None);
 
         let token = self.consume(LBrace)?;
@@ -115,12 +198,13 @@ impl<'a> Parser<'a> {
     fn parse_statements(
         &mut self,
         subroutine_catg: Option<TokenTypeCategory>,
-    ) -> Result<ParseTreeNodeData, String> {
+    ) -> Result<ParseTreeNodeData, Diagnostic> {
         let mut statements = ParseTreeNodeData::new("statements", None);
+        let error_count = self.errors.len();
         loop {
             let next_token = self.peek();
             if next_token.is_none() {
-                return Err("Unexpected end of input while parsing statements".to_string());
+                return Err(Diagnostic::error("Unexpected end of input while parsing statements"));
             }
             let next_token = next_token.unwrap();
             let statement = match next_token.token_type.get_category() {
@@ -129,31 +213,54 @@ impl<'a> Parser<'a> {
                 While => self.parse_while_statement(),
                 Do => self.parse_do_statement(),
                 Return => self.parse_return_statement(),
-                _ => break,
+                For => self.parse_for_statement(),
+                Break => self.parse_jump_statement(Break, "breakStatement"),
+                Continue => self.parse_jump_statement(Continue, "continueStatement"),
+                // The end of the block, or of a subroutine whose closing brace is missing
+                RBrace | Constructor | Function | Method => break,
+                _ => {
+                    let token = self.consume_any()?;
+                    Err(Self::unexpected_token(
+                        &token,
+                        format!("Expected statement, found {:?}", token.token_type),
+                    ))
+                }
             };
-            statements.add_child(statement?);
+            match statement {
+                Ok(statement) => statements.add_child(statement),
+                Err(error) => {
+                    self.errors.push(error);
+                    self.synchronize();
+                }
+            }
+        }
+
+        // Statements that were skipped cannot be checked
+        if self.errors.len() > error_count {
+            return Ok(statements);
         }
 
         match subroutine_catg {
             Some(Constructor) => {
                 // A constructor must end with a return this statement
                 let tail_statements = self.find_tail_statements(&statements);
-                if tail_statements
+                if let Some(stmt) = tail_statements
                     .iter()
-                    .any(|stmt| !self.is_return_stmt_with_this(stmt))
+                    .find(|stmt| !self.is_return_stmt_with_this(stmt))
                 {
-                    return Err("Constructor must end with a 'return this' statement".to_string());
+                    return Err(Self::statement_error(stmt, "Constructor must end with a 'return this' statement"));
                 }
             }
             Some(_) => {
                 let tail_statements = self.find_tail_statements(&statements);
-                if tail_statements
+                if let Some(stmt) = tail_statements
                     .iter()
-                    .any(|stmt| !self.is_return_statement(stmt))
+                    .find(|stmt| !self.is_return_statement(stmt))
                 {
-                    return Err(
-                        "Functions or methods must end with a 'return' statement".to_string()
-                    );
+                    return Err(Self::statement_error(
+                        stmt,
+                        "Functions or methods must end with a 'return' statement",
+                    ));
                 }
             }
             _ => {}
@@ -170,7 +277,11 @@ impl<'a> Parser<'a> {
         if last_statement.is_none() {
             return vec![];
         }
-        let last_statement = last_statement.unwrap();
+        self.find_statement_tails(last_statement.unwrap())
+    }
+
+    /// The statements that end the execution of `statement`.
+    fn find_statement_tails<'b>(&self, last_statement: &'b ParseTreeNode) -> Vec<&'b ParseTreeNodeData> {
         if let Some(if_statement) = last_statement.apply_action(&CheckName {
             name: "ifStatement".to_string(),
         }) {
@@ -185,7 +296,7 @@ impl<'a> Parser<'a> {
             //  - statements (if block)
             //  - '}' token
             //  - optional 'else' token
-            //  - optional '{' token
+            //  - optional '{' token, or an ifStatement in extended Jack
             //  - optional statements (else block)
             //  - optional '}' token
 
@@ -202,12 +313,21 @@ impl<'a> Parser<'a> {
             }
 
             // Check for else block
-            if let Some(_) = if_statement.apply_actions(&[
-                NavigateToChildByIndex { index: 7 },
-                CheckTokenTypeCategory {
-                    token_type_category: Else,
-                },
-            ]) {
+            if if_statement
+                .apply_actions(&[
+                    NavigateToChildByIndex { index: 7 },
+                    CheckTokenTypeCategory {
+                        token_type_category: Else,
+                    },
+                ])
+                .is_some()
+            {
+                if let Some(else_if_statement) = if_statement.apply_actions(&[
+                    NavigateToChildByIndex { index: 8 },
+                    CheckName { name: "ifStatement".to_string() },
+                ]) {
+                    tail_statements.extend(self.find_statement_tails(else_if_statement));
+                }
                 let else_block_statements = if_statement
                     .apply_action(&NavigateToChildByIndex { index: 9 })
                     .and_then(|node| match node {
@@ -228,6 +348,15 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Reports an error at the first token of a statement.
+    fn statement_error(statement: &ParseTreeNodeData, message: &str) -> Diagnostic {
+        let diagnostic = Diagnostic::error(message);
+        match statement.children.first() {
+            Some(ParseTreeNode::Terminal(token)) => diagnostic.with_span(Span::from_token(token)),
+            _ => diagnostic,
+        }
+    }
+
     fn is_return_statement(&self, statement: &ParseTreeNodeData) -> bool {
         statement.name == "returnStatement"
     }
@@ -256,7 +385,7 @@ impl<'a> Parser<'a> {
         ]).is_some()
     }
 
-    fn parse_return_statement(&mut self) -> Result<ParseTreeNodeData, String> {
+    fn parse_return_statement(&mut self) -> Result<ParseTreeNodeData, Diagnostic> {
         let mut return_statement = ParseTreeNodeData::new("returnStatement", None);
 
         let token = self.consume(Return)?;
@@ -269,7 +398,7 @@ impl<'a> Parser<'a> {
                 return_statement.add_child(expr);
             }
         } else {
-            return Err("Unexpected end of input while parsing return statement".to_string());
+            return Err(Diagnostic::error("Unexpected end of input while parsing return statement"));
         }
 
         let token = self.consume(Semicolon)?;
@@ -278,7 +407,17 @@ impl<'a> Parser<'a> {
         Ok(return_statement)
     }
 
-    fn parse_do_statement(&mut self) -> Result<ParseTreeNodeData, String> {
+    fn parse_do_statement(&mut self) -> Result<ParseTreeNodeData, Diagnostic> {
+        let mut do_statement = self.parse_do_clause()?;
+
+        let token = self.consume(Semicolon)?;
+        do_statement.add_token(token);
+
+        Ok(do_statement)
+    }
+
+    /// Parses a do statement without its semicolon.
+    fn parse_do_clause(&mut self) -> Result<ParseTreeNodeData, Diagnostic> {
         let mut do_statement = ParseTreeNodeData::new("doStatement", None);
 
         let token = self.consume(Do)?;
@@ -286,13 +425,71 @@ impl<'a> Parser<'a> {
 
         self.parse_subroutine_call(&mut do_statement)?;
 
+        Ok(do_statement)
+    }
+
+    // forStatement: 'for' '(' (letStatement | doStatement | ';') expression? ';'
+    //               (letClause | doClause)? ')' '{' statements '}'
+    fn parse_for_statement(&mut self) -> Result<ParseTreeNodeData, Diagnostic> {
+        let mut for_statement = ParseTreeNodeData::new("forStatement", None);
+
+        let token = self.consume(For)?;
+        for_statement.add_token(token);
+
+        let token = self.consume(LParen)?;
+        for_statement.add_token(token);
+
+        // The initialization is a complete statement, including its semicolon
+        match self.peek().map(|token| token.token_type.get_category()) {
+            Some(Let) => for_statement.add_child(self.parse_let_statement()?),
+            Some(Do) => for_statement.add_child(self.parse_do_statement()?),
+            _ => for_statement.add_token(self.consume(Semicolon)?),
+        }
+
+        if self.peek().is_some_and(|token| token.token_type.get_category() != Semicolon) {
+            for_statement.add_child(self.parse_expression()?);
+        }
         let token = self.consume(Semicolon)?;
-        do_statement.add_token(token);
+        for_statement.add_token(token);
 
-        Ok(do_statement)
+        match self.peek().map(|token| token.token_type.get_category()) {
+            Some(Let) => for_statement.add_child(self.parse_let_clause()?),
+            Some(Do) => for_statement.add_child(self.parse_do_clause()?),
+            _ => {}
+        }
+        let token = self.consume(RParen)?;
+        for_statement.add_token(token);
+
+        let token = self.consume(LBrace)?;
+        for_statement.add_token(token);
+
+        let statements = self.parse_statements(None)?;
+        for_statement.add_child(statements);
+
+        let token = self.consume(RBrace)?;
+        for_statement.add_token(token);
+
+        Ok(for_statement)
+    }
+
+    /// Parses `break;` or `continue;`.
+    fn parse_jump_statement(
+        &mut self,
+        keyword: TokenTypeCategory,
+        name: &str,
+    ) -> Result<ParseTreeNodeData, Diagnostic> {
+        let mut jump_statement = ParseTreeNodeData::new(name, None);
+
+        let token = self.consume(keyword)?;
+        jump_statement.add_token(token);
+
+        let token = self.consume(Semicolon)?;
+        jump_statement.add_token(token);
+
+        Ok(jump_statement)
     }
 
-    fn parse_while_statement(&mut self) -> Result<ParseTreeNodeData, String> {
+    fn parse_while_statement(&mut self) -> Result<ParseTreeNodeData, Diagnostic> {
         let mut while_statement = ParseTreeNodeData::new("whileStatement", None);
 
         let token = self.consume(While)?;
@@ -319,7 +516,7 @@ impl<'a> Parser<'a> {
         Ok(while_statement)
     }
 
-    fn parse_if_statement(&mut self) -> Result<ParseTreeNodeData, String> {
+    fn parse_if_statement(&mut self) -> Result<ParseTreeNodeData, Diagnostic> {
         let mut if_statement = ParseTreeNodeData::new("ifStatement", None);
 
         let token = self.consume(If)?;
@@ -344,26 +541,43 @@ impl<'a> Parser<'a> {
         if_statement.add_token(token);
 
         let next_token = self.peek();
-        if let Some(token) = next_token {
-            if token.token_type.get_category() == Else {
-                let token = self.consume(Else)?;
-                if_statement.add_token(token);
+        if let Some(token) = next_token
+            && token.token_type.get_category() == Else
+        {
+            let token = self.consume(Else)?;
+            if_statement.add_token(token);
+
+            if self.language_level == LanguageLevel::Extended
+                && self.peek().is_some_and(|token| token.token_type.get_category() == If)
+            {
+                if_statement.add_child(self.parse_if_statement()?);
+                return Ok(if_statement);
+            }
 
-                let token = self.consume(LBrace)?;
-                if_statement.add_token(token);
+            let token = self.consume(LBrace)?;
+            if_statement.add_token(token);
 
-                let statements = self.parse_statements(None)?;
-                if_statement.add_child(statements);
+            let statements = self.parse_statements(None)?;
+            if_statement.add_child(statements);
 
-                let token = self.consume(RBrace)?;
-                if_statement.add_token(token);
-            }
+            let token = self.consume(RBrace)?;
+            if_statement.add_token(token);
         }
 
         Ok(if_statement)
     }
 
-    fn parse_let_statement(&mut self) -> Result<ParseTreeNodeData, String> {
+    fn parse_let_statement(&mut self) -> Result<ParseTreeNodeData, Diagnostic> {
+        let mut let_statement = self.parse_let_clause()?;
+
+        let token = self.consume(Semicolon)?;
+        let_statement.add_token(token);
+
+        Ok(let_statement)
+    }
+
+    /// Parses a let statement without its semicolon.
+    fn parse_let_clause(&mut self) -> Result<ParseTreeNodeData, Diagnostic> {
         let mut let_statement = ParseTreeNodeData::new("letStatement", None);
 
         let token = self.consume(Let)?;
@@ -383,7 +597,7 @@ impl<'a> Parser<'a> {
                 let_statement.add_token(token);
             }
         } else {
-            return Err("Unexpected end of input while parsing let statement".to_string());
+            return Err(Diagnostic::error("Unexpected end of input while parsing let statement"));
         }
 
         let token = self.consume(Equal)?;
@@ -392,13 +606,10 @@ impl<'a> Parser<'a> {
         let expr = self.parse_expression()?;
         let_statement.add_child(expr);
 
-        let token = self.consume(Semicolon)?;
-        let_statement.add_token(token);
-
         Ok(let_statement)
     }
 
-    fn parse_expression(&mut self) -> Result<ParseTreeNodeData, String> {
+    fn parse_expression(&mut self) -> Result<ParseTreeNodeData, Diagnostic> {
         let mut expression = ParseTreeNodeData::new("expression", None);
 
         self.parse_term(&mut expression)?;
@@ -421,14 +632,82 @@ impl<'a> Parser<'a> {
             }
         }
 
+        match self.precedence {
+            Precedence::LeftToRight => self.warn_about_precedence(&expression),
+            Precedence::Standard => {
+                expression.children = Self::group_by_precedence(std::mem::take(&mut expression.children))
+            }
+        }
+
         Ok(expression)
     }
 
-    fn parse_term(&mut self, expression: &mut ParseTreeNodeData) -> Result<(), String> {
+    /// Warns if an operator binds tighter than one before it, since only then
+    /// does evaluating from left to right differ from standard precedence.
+    fn warn_about_precedence(&mut self, expression: &ParseTreeNodeData) {
+        let mut loosest: Option<&JackToken> = None;
+        for child in expression.children.iter().skip(1).step_by(2) {
+            let ParseTreeNode::Terminal(operator) = child else {
+                continue;
+            };
+            match loosest {
+                Some(earlier) if binding_power(operator) > binding_power(earlier) => {
+                    self.warnings.push(
+                        Diagnostic::warning(format!(
+                            "'{}' is applied before '{}' because Jack evaluates operators from left to right",
+                            earlier.lexeme, operator.lexeme
+                        ))
+                        .with_span(Span::from_token(operator))
+                        .with_note("add parentheses, or compile with standard operator precedence"),
+                    );
+                    return;
+                }
+                _ => loosest = Some(operator),
+            }
+        }
+    }
+
+    /// Groups alternating terms and operators so that the loosest operators
+    /// stay at the top level and every run of tighter ones becomes one term.
+    fn group_by_precedence(children: Vec<ParseTreeNode>) -> Vec<ParseTreeNode> {
+        let operator_power = |child: &ParseTreeNode| match child {
+            ParseTreeNode::Terminal(token) => binding_power(token),
+            ParseTreeNode::NonTerminal(_) => u8::MAX,
+        };
+        let Some(loosest) = children.iter().skip(1).step_by(2).map(operator_power).min() else {
+            return children;
+        };
+
+        let mut grouped = vec![];
+        let mut operand = vec![];
+        for (i, child) in children.into_iter().enumerate() {
+            if i % 2 == 1 && operator_power(&child) == loosest {
+                grouped.push(Self::operand_term(std::mem::take(&mut operand)));
+                grouped.push(child);
+            } else {
+                operand.push(child);
+            }
+        }
+        grouped.push(Self::operand_term(operand));
+        grouped
+    }
+
+    fn operand_term(mut children: Vec<ParseTreeNode>) -> ParseTreeNode {
+        if children.len() == 1 {
+            return children.remove(0);
+        }
+        let mut expression = ParseTreeNodeData::new("expression", None);
+        expression.children = Self::group_by_precedence(children);
+        let mut term = ParseTreeNodeData::new("term", None);
+        term.add_child(expression);
+        ParseTreeNode::NonTerminal(term)
+    }
+
+    fn parse_term(&mut self, expression: &mut ParseTreeNodeData) -> Result<(), Diagnostic> {
         let mut term = ParseTreeNodeData::new("term", None);
         let next_token = self.peek();
         if next_token.is_none() {
-            return Err("Unexpected end of input while parsing term".to_string());
+            return Err(Diagnostic::error("Unexpected end of input while parsing term"));
         }
         let next_token = next_token.unwrap();
         match next_token.token_type.get_category() {
@@ -454,7 +733,16 @@ impl<'a> Parser<'a> {
             }
             Identifier => {
                 let lookahead = self.peek_nth(1);
+                let is_call = self.peek_nth(3).is_some_and(|token| token.token_type.get_category() == LParen);
                 match lookahead {
+                    // Constants of other classes and enum members, like `Direction.UP`
+                    Some(token)
+                        if token.token_type.get_category() == Dot
+                            && !is_call
+                            && self.language_level == LanguageLevel::Extended =>
+                    {
+                        self.parse_qualified_name(&mut term)?;
+                    }
                     Some(token)
                         if token.token_type.get_category() == LParen
                             || token.token_type.get_category() == Dot =>
@@ -470,7 +758,12 @@ impl<'a> Parser<'a> {
                     }
                 }
             }
-            _ => return Err("Got unknown token while parsing term".to_string()),
+            _ => {
+                return Err(Self::unexpected_token(
+                    &next_token,
+                    "Got unknown token while parsing term".to_string(),
+                ))
+            }
         }
 
         expression.add_child(term);
@@ -478,7 +771,16 @@ impl<'a> Parser<'a> {
         Ok(())
     }
 
-    fn parse_array_access(&mut self, data: &mut ParseTreeNodeData) -> Result<(), String> {
+    fn parse_qualified_name(&mut self, data: &mut ParseTreeNodeData) -> Result<(), Diagnostic> {
+        data.add_token(self.consume(Identifier)?);
+        while self.peek().is_some_and(|token| token.token_type.get_category() == Dot) {
+            data.add_token(self.consume(Dot)?);
+            data.add_token(self.consume(Identifier)?);
+        }
+        Ok(())
+    }
+
+    fn parse_array_access(&mut self, data: &mut ParseTreeNodeData) -> Result<(), Diagnostic> {
         let token = self.consume(Identifier)?;
         data.add_token(token);
 
@@ -493,24 +795,21 @@ impl<'a> Parser<'a> {
         Ok(())
     }
 
-    fn parse_subroutine_call(&mut self, data: &mut ParseTreeNodeData) -> Result<(), String> {
+    fn parse_subroutine_call(&mut self, data: &mut ParseTreeNodeData) -> Result<(), Diagnostic> {
         let token = self.consume(Identifier)?;
         data.add_token(token);
 
         let next_token = self.peek();
         if let Some(token) = next_token {
-            match token.token_type.get_category() {
-                Dot => {
-                    let token = self.consume(Dot)?;
-                    data.add_token(token);
+            if token.token_type.get_category() == Dot {
+                let token = self.consume(Dot)?;
+                data.add_token(token);
 
-                    let token = self.consume(Identifier)?;
-                    data.add_token(token);
-                }
-                _ => {}
+                let token = self.consume(Identifier)?;
+                data.add_token(token);
             }
         } else {
-            return Err("Unexpected end of input while parsing subroutine call".to_string());
+            return Err(Diagnostic::error("Unexpected end of input while parsing subroutine call"));
         }
 
         let token = self.consume(LParen)?;
@@ -524,7 +823,7 @@ impl<'a> Parser<'a> {
         Ok(())
     }
 
-    fn parse_expression_list(&mut self) -> Result<ParseTreeNodeData, String> {
+    fn parse_expression_list(&mut self) -> Result<ParseTreeNodeData, Diagnostic> {
         let mut expression_list = ParseTreeNodeData::new("expressionList", None);
 
         loop {
@@ -534,7 +833,7 @@ impl<'a> Parser<'a> {
                     break;
                 }
             } else {
-                return Err("Unexpected end of input while parsing expression list".to_string());
+                return Err(Diagnostic::error("Unexpected end of input while parsing expression list"));
             }
 
             let expr = self.parse_expression()?;
@@ -548,7 +847,7 @@ impl<'a> Parser<'a> {
                     break;
                 }
             } else {
-                return Err("Unexpected end of input while parsing expression list".to_string());
+                return Err(Diagnostic::error("Unexpected end of input while parsing expression list"));
             }
         }
 
@@ -558,43 +857,49 @@ impl<'a> Parser<'a> {
     fn parse_var_declarations(
         &mut self,
         subroutine_dec: &mut ParseTreeNodeData,
-    ) -> Result<(), String> {
+    ) -> Result<(), Diagnostic> {
         loop {
             let next_token = self.peek();
             if next_token.is_none() {
-                return Err(
-                    "Unexpected end of input while parsing variable declarations".to_string(),
-                );
+                return Err(Diagnostic::error("Unexpected end of input while parsing variable declarations"));
             }
             let next_token = next_token.unwrap();
             match next_token.token_type.get_category() {
-                Var => {
-                    let mut var_dec = ParseTreeNodeData::new("varDec", None);
+                Var => match self.parse_var_declaration() {
+                    Ok(var_dec) => subroutine_dec.add_child(var_dec),
+                    Err(error) => {
+                        self.errors.push(error);
+                        self.synchronize();
+                    }
+                },
+                _ => break,
+            }
+        }
+
+        Ok(())
+    }
 
-                    let token = self.consume(Var)?;
-                    var_dec.add_token(token);
+    fn parse_var_declaration(&mut self) -> Result<ParseTreeNodeData, Diagnostic> {
+        let mut var_dec = ParseTreeNodeData::new("varDec", None);
 
-                    let token = self.consume_type(false)?;
-                    var_dec.add_token(token);
+        let token = self.consume(Var)?;
+        var_dec.add_token(token);
 
-                    self.add_comma_separated_identifiers(&mut var_dec)?;
+        let token = self.consume_type(false)?;
+        var_dec.add_token(token);
 
-                    let token = self.consume(Semicolon)?;
-                    var_dec.add_token(token);
+        self.add_comma_separated_identifiers(&mut var_dec)?;
 
-                    subroutine_dec.add_child(var_dec);
-                }
-                _ => break,
-            }
-        }
+        let token = self.consume(Semicolon)?;
+        var_dec.add_token(token);
 
-        Ok(())
+        Ok(var_dec)
     }
 
     fn parse_parameter_list(
         &mut self,
         subroutine_dec: &mut ParseTreeNodeData,
-    ) -> Result<(), String> {
+    ) -> Result<(), Diagnostic> {
         let mut param_list = ParseTreeNodeData::new("parameterList", None);
 
         let next_token = self.peek();
@@ -604,7 +909,7 @@ impl<'a> Parser<'a> {
                 return Ok(());
             }
         } else {
-            return Err("Unexpected end of input while parsing parameter list".to_string());
+            return Err(Diagnostic::error("Unexpected end of input while parsing parameter list"));
         }
 
         loop {
@@ -623,7 +928,7 @@ impl<'a> Parser<'a> {
                     break;
                 }
             } else {
-                return Err("Unexpected end of input in parameter list".to_string());
+                return Err(Diagnostic::error("Unexpected end of input in parameter list"));
             }
         }
 
@@ -635,43 +940,105 @@ impl<'a> Parser<'a> {
     fn parse_class_var_declarations(
         &mut self,
         class_data: &mut ParseTreeNodeData,
-    ) -> Result<(), String> {
+    ) -> Result<(), Diagnostic> {
         loop {
             let next_token = self.peek();
             if next_token.is_none() {
-                return Err(
-                    "Unexpected end of input while parsing variable declarations".to_string(),
-                );
+                return Err(Diagnostic::error("Unexpected end of input while parsing variable declarations"));
             }
             let next_token = next_token.unwrap();
             match next_token.token_type.get_category() {
-                Static | Field => {
-                    let mut class_var_dec = ParseTreeNodeData::new("classVarDec", None);
+                Static | Field | Const | Enum => {
+                    let declaration = match next_token.token_type.get_category() {
+                        Const => self.parse_constant_declaration(),
+                        Enum => self.parse_enum_declaration(),
+                        _ => self.parse_class_var_declaration(),
+                    };
+                    match declaration {
+                        Ok(declaration) => class_data.add_child(declaration),
+                        Err(error) => {
+                            self.errors.push(error);
+                            self.synchronize();
+                        }
+                    }
+                }
+                _ => break,
+            }
+        }
 
-                    let token = self.consume_any_of(&[Static, Field])?;
-                    class_var_dec.add_token(token);
+        Ok(())
+    }
 
-                    let token = self.consume_type(false)?;
-                    class_var_dec.add_token(token);
+    fn parse_class_var_declaration(&mut self) -> Result<ParseTreeNodeData, Diagnostic> {
+        let mut class_var_dec = ParseTreeNodeData::new("classVarDec", None);
 
-                    self.add_comma_separated_identifiers(&mut class_var_dec)?;
+        let token = self.consume_any_of(&[Static, Field])?;
+        class_var_dec.add_token(token);
 
-                    let token = self.consume(Semicolon)?;
-                    class_var_dec.add_token(token);
+        let token = self.consume_type(false)?;
+        class_var_dec.add_token(token);
 
-                    class_data.add_child(class_var_dec);
-                }
-                _ => break,
+        self.add_comma_separated_identifiers(&mut class_var_dec)?;
+
+        let token = self.consume(Semicolon)?;
+        class_var_dec.add_token(token);
+
+        Ok(class_var_dec)
+    }
+
+    fn parse_constant_declaration(&mut self) -> Result<ParseTreeNodeData, Diagnostic> {
+        let mut constant_dec = ParseTreeNodeData::new("classConstDec", None);
+
+        constant_dec.add_token(self.consume(Const)?);
+        constant_dec.add_token(self.consume_type(false)?);
+        constant_dec.add_token(self.consume(Identifier)?);
+        constant_dec.add_token(self.consume(Equal)?);
+        constant_dec.add_child(self.parse_constant_value()?);
+        constant_dec.add_token(self.consume(Semicolon)?);
+
+        Ok(constant_dec)
+    }
+
+    /// Parses the value of a constant, which has to be known at compile time.
+    fn parse_constant_value(&mut self) -> Result<ParseTreeNodeData, Diagnostic> {
+        let mut term = ParseTreeNodeData::new("term", None);
+        match self.peek() {
+            Some(token) if token.token_type.get_category() == Minus => {
+                term.add_token(self.consume(Minus)?);
+                let mut negated_term = ParseTreeNodeData::new("term", None);
+                negated_term.add_token(self.consume(IntegerConstant)?);
+                term.add_child(negated_term);
             }
+            Some(token) if matches!(token.token_type.get_category(), IntegerConstant | True | False | Null) => {
+                term.add_token(self.consume_any()?);
+            }
+            Some(token) => {
+                return Err(Self::unexpected_token(
+                    &token,
+                    format!("Expected integer, character or boolean constant, found {:?}", token.token_type),
+                ))
+            }
+            None => return Err(Diagnostic::error("Unexpected end of input")),
         }
+        Ok(term)
+    }
 
-        Ok(())
+    fn parse_enum_declaration(&mut self) -> Result<ParseTreeNodeData, Diagnostic> {
+        let mut enum_dec = ParseTreeNodeData::new("enumDec", None);
+
+        enum_dec.add_token(self.consume(Enum)?);
+        enum_dec.add_token(self.consume(Identifier)?);
+        enum_dec.add_token(self.consume(LBrace)?);
+        self.add_comma_separated_identifiers(&mut enum_dec)?;
+        enum_dec.add_token(self.consume(RBrace)?);
+
+        Ok(enum_dec)
     }
 
     fn add_comma_separated_identifiers(
         &mut self,
         data: &mut ParseTreeNodeData,
-    ) -> Result<(), String> {
+    ) -> Result<(), Diagnostic> {
         let token = self.consume(Identifier)?;
         data.add_token(token);
 
@@ -688,9 +1055,7 @@ impl<'a> Parser<'a> {
                     break;
                 }
             } else {
-                return Err(
-                    "Unexpected end of input while parsing comma-separated identifiers".to_string(),
-                );
+                return Err(Diagnostic::error("Unexpected end of input while parsing comma-separated identifiers"));
             }
         }
         Ok(())
@@ -704,39 +1069,90 @@ impl<'a> Parser<'a> {
         self.stream.peek_nth(n)
     }
 
-    fn consume_type(&mut self, include_void: bool) -> Result<JackToken, String> {
+    fn consume_type(&mut self, include_void: bool) -> Result<JackToken, Diagnostic> {
         if include_void {
             return self.consume_any_of(&[Void, Int, Char, Boolean, Identifier]);
         }
         self.consume_any_of(&[Int, Char, Boolean, Identifier])
     }
 
-    fn consume(&mut self, expected: TokenTypeCategory) -> Result<JackToken, String> {
-        match self.stream.advance() {
-            Some(token) if token.token_type.get_category() == expected => Ok(token),
-            Some(token) => Err(format!(
-                "[{}, {}]: Expected {:?}, found {:?}",
-                token.line, token.column, expected, token.token_type
+    /// Skips the tokens of a statement or declaration that failed to parse,
+    /// up to and including its `;`, or up to a `}` or a keyword that starts
+    /// a new statement or declaration.
+    fn synchronize(&mut self) {
+        while let Some(token) = self.peek() {
+            match token.token_type.get_category() {
+                Semicolon => {
+                    self.stream.advance();
+                    return;
+                }
+                Let | If | While | Do | Return | For | Break | Continue | Var | RBrace | Static
+                | Field | Const | Enum | Constructor | Function | Method => return,
+                _ => {
+                    self.stream.advance();
+                }
+            }
+        }
+    }
+
+    /// Skips the rest of a subroutine declaration that failed to parse.
+    fn skip_to_subroutine_declaration(&mut self) {
+        while let Some(token) = self.peek() {
+            if matches!(token.token_type.get_category(), Constructor | Function | Method) {
+                return;
+            }
+            self.stream.advance();
+        }
+    }
+
+    // Mismatched tokens are left in the stream, so that recovery can resume at them
+
+    fn consume(&mut self, expected: TokenTypeCategory) -> Result<JackToken, Diagnostic> {
+        match self.peek() {
+            Some(token) if token.token_type.get_category() == expected => Ok(self.consume_any()?),
+            Some(token) => Err(Self::unexpected_token(
+                &token,
+                format!("Expected {:?}, found {:?}", expected, token.token_type),
             )),
-            None => Err("Unexpected end of input".to_string()),
+            None => Err(Diagnostic::error("Unexpected end of input")),
         }
     }
 
-    fn consume_any_of(&mut self, expected: &[TokenTypeCategory]) -> Result<JackToken, String> {
-        match self.stream.advance() {
-            Some(token) if expected.contains(&token.token_type.get_category()) => Ok(token),
-            Some(token) => Err(format!(
-                "Expected one of {:?}, found {:?}",
-                expected, token.token_type
+    fn consume_any_of(&mut self, expected: &[TokenTypeCategory]) -> Result<JackToken, Diagnostic> {
+        match self.peek() {
+            Some(token) if expected.contains(&token.token_type.get_category()) => Ok(self.consume_any()?),
+            Some(token) => Err(Self::unexpected_token(
+                &token,
+                format!("Expected one of {:?}, found {:?}", expected, token.token_type),
             )),
-            None => Err("Unexpected end of input".to_string()),
+            None => Err(Diagnostic::error("Unexpected end of input")),
         }
     }
 
-    fn consume_any(&mut self) -> Result<JackToken, String> {
+    fn consume_any(&mut self) -> Result<JackToken, Diagnostic> {
         self.stream
             .advance()
-            .ok_or("Unexpected end of input".to_string())
+            .ok_or_else(|| Diagnostic::error("Unexpected end of input"))
+    }
+
+    /// Reports a token that does not fit the grammar, or the lexer error it
+    /// stands for.
+    fn unexpected_token(token: &JackToken, message: String) -> Diagnostic {
+        let message = match &token.token_type {
+            TokenType::Error { message } => message.clone(),
+            _ => message,
+        };
+        Diagnostic::error(message).with_span(Span::from_token(token))
+    }
+}
+
+/// How tightly a binary operator binds with standard precedence.
+fn binding_power(operator: &JackToken) -> u8 {
+    match operator.token_type.get_category() {
+        Asterisk | Slash => 3,
+        Plus | Minus => 2,
+        LessThan | GreaterThan | Equal => 1,
+        _ => 0,
     }
 }
 
@@ -827,4 +1243,126 @@ mod tests {
             class_ast.err().unwrap()
         );
     }
+
+    #[test]
+    fn test_parse_errors_have_spans() {
+        let parse = |code: &str| {
+            let mut char_stream = StringCharStream::new(code);
+            let mut lexer = Lexer::new(&mut char_stream);
+            Parser::new(&mut lexer).parse_class().err().unwrap().remove(0)
+        };
+
+        let error = parse("class A {\n  constructor A new() {\n    return 1;\n  }\n}");
+        assert_eq!(error.message, "Constructor must end with a 'return this' statement");
+        assert_eq!(error.span, Some(Span { line: 3, column: 5, length: 6 }));
+
+        let error = parse("class A {\n  function void f() {\n    let x = 1 # 2;\n  }\n}");
+        assert_eq!(error.to_string(), "3:15: error: Unexpected character: '#'");
+    }
+
+    #[test]
+    fn test_parse_class_recovers_from_errors() {
+        let code = r#"
+        class A {
+            field int x
+            field int y;
+
+            method void f() {
+                let x = 1 +;
+                do Output.printInt(x);
+                let y = ;
+                return;
+            }
+
+            function int g(int a {
+                return a;
+            }
+
+            function void h() {
+                var int ;
+                return;
+            }
+        }
+        "#;
+        let mut char_stream = StringCharStream::new(code);
+        let mut lexer = Lexer::new(&mut char_stream);
+        let mut parser = Parser::new(&mut lexer);
+
+        let (parse_tree, errors) = parser.parse_class_with_errors();
+
+        let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            errors,
+            vec![
+                "4:13: error: Expected Semicolon, found Field",
+                "7:28: error: Got unknown token while parsing term",
+                "9:25: error: Got unknown token while parsing term",
+                "13:34: error: Expected RParen, found LBrace",
+                "18:25: error: Expected Identifier, found Semicolon",
+            ]
+        );
+        let ParseTreeNode::NonTerminal(class_data) = parse_tree else {
+            panic!("Expected class node");
+        };
+        let members: Vec<&str> = class_data
+            .children
+            .iter()
+            .filter_map(|child| match child {
+                ParseTreeNode::NonTerminal(data) => Some(data.name.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(members, vec!["classVarDec", "subroutineDec", "subroutineDec"]);
+    }
+
+    #[test]
+    fn test_language_levels() {
+        let parse = |code: &str, level: LanguageLevel| {
+            let mut char_stream = StringCharStream::new(code);
+            let mut lexer = Lexer::new(&mut char_stream);
+            lexer.set_language_level(level);
+            let mut parser = Parser::new(&mut lexer);
+            parser.set_language_level(level);
+            parser.parse_class().map(|_| ()).map_err(|errors| errors[0].to_string())
+        };
+
+        // Standard Jack knows no extended keywords
+        let code = "class A {\n  function void f() {\n    var int for;\n    let for = 1;\n    return;\n  }\n}";
+        assert!(parse(code, LanguageLevel::Standard).is_ok());
+        assert!(parse(code, LanguageLevel::Extended).is_err());
+
+        let code = "class A {\n  function void f() {\n    if (true) { } else if (false) { }\n    return;\n  }\n}";
+        assert_eq!(
+            parse(code, LanguageLevel::Standard).unwrap_err(),
+            "3:24: error: Expected LBrace, found If"
+        );
+        assert!(parse(code, LanguageLevel::Extended).is_ok());
+
+        let code = "class A {\n  function void f() {\n    for (;;) { break; continue; }\n    return;\n  }\n}";
+        assert!(parse(code, LanguageLevel::Standard).is_err());
+        assert!(parse(code, LanguageLevel::Extended).is_ok());
+    }
+
+    #[test]
+    fn test_warn_about_left_to_right_evaluation() {
+        let code = "class A {\n  function void f(int a) {\n    let a = a * 2 + 1;\n    let a = a + (2 * a);\n    let a = a + a - 2 * a;\n    let a = a = a + 1 | false;\n    return;\n  }\n}";
+        let warnings = |precedence: Precedence| {
+            let mut char_stream = StringCharStream::new(code);
+            let mut lexer = Lexer::new(&mut char_stream);
+            let mut parser = Parser::new(&mut lexer);
+            parser.set_precedence(precedence);
+            parser.parse_class().map(|_| ()).unwrap();
+            let warnings: Vec<String> = parser.take_warnings().iter().map(|w| w.to_string()).collect();
+            warnings
+        };
+
+        assert_eq!(
+            warnings(Precedence::LeftToRight),
+            vec![
+                "5:23: warning: '-' is applied before '*' because Jack evaluates operators from left to right",
+                "6:19: warning: '=' is applied before '+' because Jack evaluates operators from left to right",
+            ]
+        );
+        assert!(warnings(Precedence::Standard).is_empty());
+    }
 }
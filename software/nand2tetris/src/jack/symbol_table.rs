@@ -14,7 +14,9 @@ pub type SymbolTableRef = Rc<RefCell<SymbolTable>>;
 
 #[derive(Debug, Clone)]
 pub struct SymbolTableEntry {
+    /// `Segment::Constant` for constants, which need no memory.
     pub segment: Segment,
+    /// The value of a constant as a 16 bit pattern.
     pub index: u16,
     pub var_type: Type,
 }
@@ -59,6 +61,15 @@ impl SymbolTable {
         self.entries.insert(name, entry);
     }
 
+    pub fn add_constant(&mut self, name: String, var_type: Type, value: i16) {
+        let entry = SymbolTableEntry {
+            segment: Segment::Constant,
+            index: value as u16,
+            var_type,
+        };
+        self.entries.insert(name, entry);
+    }
+
     pub fn add_parameter(&mut self, name: String, var_type: Type) {
         self.add_var(Segment::Argument, name, var_type);
     }
@@ -21,6 +21,11 @@ pub enum TokenType {
     Else,
     While,
     Return,
+    For,
+    Break,
+    Continue,
+    Const,
+    Enum,
     LBrace,
     RBrace,
     LParen,
@@ -70,6 +75,11 @@ impl TokenType {
             TokenType::Else => TokenTypeCategory::Else,
             TokenType::While => TokenTypeCategory::While,
             TokenType::Return => TokenTypeCategory::Return,
+            TokenType::For => TokenTypeCategory::For,
+            TokenType::Break => TokenTypeCategory::Break,
+            TokenType::Continue => TokenTypeCategory::Continue,
+            TokenType::Const => TokenTypeCategory::Const,
+            TokenType::Enum => TokenTypeCategory::Enum,
             TokenType::LBrace => TokenTypeCategory::LBrace,
             TokenType::RBrace => TokenTypeCategory::RBrace,
             TokenType::LParen => TokenTypeCategory::LParen,
@@ -120,6 +130,11 @@ pub enum TokenTypeCategory {
     Else,
     While,
     Return,
+    For,
+    Break,
+    Continue,
+    Const,
+    Enum,
     LBrace,
     RBrace,
     LParen,
@@ -1,7 +1,21 @@
 mod token_type;
 mod lexer;
 mod parser;
+mod linker;
+mod stack_checker;
+mod inliner;
+mod json;
+pub mod source_map;
+pub mod bytecode;
 pub mod ast;
 pub mod code_writer;
+mod c_writer;
+mod x86_writer;
+#[cfg(test)]
+mod native_tests;
+pub mod translator;
+pub mod call_graph;
 pub mod cli;
-pub use cli::Cli;
\ No newline at end of file
+pub use cli::{BytecodeCli, BytecodeCommand, CallGraphCli, Cli, convert_bytecode, write_call_graph, write_code};
+pub use source_map::{SourceLocation, SourceMap, SourceMapEntry};
+pub use translator::{translate, translate_with_source_map, HaltMode, MemoryLayout, Target, TranslateOptions, TranslateProgress, VmSource};
\ No newline at end of file
@@ -1,6 +1,6 @@
 use std::fmt::{Display, Formatter};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ASTNode {
     Program{commands: Vec<ASTNode>},
     Push{segment: Segment, index: u16},
@@ -45,6 +45,17 @@ impl ASTNode {
             ASTNode::Return => "return".to_string(),
         }
     }
+
+    /// VM code of a program, one command per line.
+    pub fn to_vm_code(&self) -> String {
+        match self {
+            ASTNode::Program { commands } => commands
+                .iter()
+                .map(|command| command.to_command_string() + "\n")
+                .collect(),
+            _ => self.to_command_string() + "\n",
+        }
+    }
 }
 
 #[derive(Debug, Eq, Hash, PartialEq, Copy, Clone)]
@@ -60,6 +71,17 @@ pub enum Segment {
 }
 
 impl Segment {
+    /// Largest index the Hack platform can honor for this segment, if bounded.
+    /// Constants are limited to what fits into an A-instruction.
+    pub fn max_index(&self) -> Option<u16> {
+        match self {
+            Segment::Constant => Some(32767),
+            Segment::Pointer => Some(1),
+            Segment::Temp => Some(7),
+            _ => None,
+        }
+    }
+
     pub fn to_string(&self) -> &str {
         match self {
             Segment::Argument => "argument",
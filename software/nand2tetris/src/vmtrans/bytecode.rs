@@ -0,0 +1,336 @@
+use crate::vmtrans::ast::{ASTNode, Segment};
+use std::collections::HashMap;
+
+/// Identifies a bytecode file.
+const MAGIC: &[u8; 4] = b"HVMB";
+const VERSION: u8 = 1;
+
+// Opcodes
+const PUSH: u8 = 0x01;
+const POP: u8 = 0x02;
+const ADD: u8 = 0x10;
+const SUB: u8 = 0x11;
+const NEG: u8 = 0x12;
+const EQ: u8 = 0x13;
+const GT: u8 = 0x14;
+const LT: u8 = 0x15;
+const AND: u8 = 0x16;
+const OR: u8 = 0x17;
+const NOT: u8 = 0x18;
+const LABEL: u8 = 0x20;
+const GOTO: u8 = 0x21;
+const IF_GOTO: u8 = 0x22;
+const FUNCTION: u8 = 0x30;
+const CALL: u8 = 0x31;
+const RETURN: u8 = 0x32;
+
+const SEGMENTS: [Segment; 8] = [
+    Segment::Argument,
+    Segment::Local,
+    Segment::Static,
+    Segment::Constant,
+    Segment::This,
+    Segment::That,
+    Segment::Pointer,
+    Segment::Temp,
+];
+
+/// Serializes named VM programs, usually one per class, into one bytecode file.
+///
+/// The file starts with the magic `HVMB` and a version byte, followed by a
+/// table of all unit, function and label names, and then by the units. A unit
+/// is its name and its commands. Every command is an opcode byte followed by
+/// its operands: a segment byte, names as indices into the string table and
+/// numbers as unsigned LEB128 varints.
+pub fn write_bytecode(units: &[(String, ASTNode)]) -> Result<Vec<u8>, String> {
+    let mut strings = StringTable::default();
+    let mut body = vec![];
+
+    write_varint(&mut body, units.len());
+    for (name, program) in units {
+        let ASTNode::Program { commands } = program else {
+            return Err("Expected Program node".to_string());
+        };
+        write_varint(&mut body, strings.index(name));
+        write_varint(&mut body, commands.len());
+        for command in commands {
+            write_command(&mut body, &mut strings, command)?;
+        }
+    }
+
+    let mut bytes = MAGIC.to_vec();
+    bytes.push(VERSION);
+    write_varint(&mut bytes, strings.strings.len());
+    for string in &strings.strings {
+        write_varint(&mut bytes, string.len());
+        bytes.extend(string.as_bytes());
+    }
+    bytes.extend(body);
+    Ok(bytes)
+}
+
+/// Reads the named VM programs of a bytecode file written by `write_bytecode`.
+pub fn read_bytecode(bytes: &[u8]) -> Result<Vec<(String, ASTNode)>, String> {
+    let mut reader = Reader { bytes, offset: 0, strings: vec![] };
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err("Not a VM bytecode file".to_string());
+    }
+    let version = reader.byte()?;
+    if version != VERSION {
+        return Err(format!("Unsupported bytecode version {version}"));
+    }
+
+    let string_count = reader.varint()?;
+    let mut strings = vec![];
+    for _ in 0..string_count {
+        let length = reader.varint()?;
+        let offset = reader.offset;
+        let string = std::str::from_utf8(reader.take(length)?)
+            .map_err(|_| format!("Invalid UTF-8 in string at offset {offset}"))?;
+        strings.push(string.to_string());
+    }
+    reader.strings = strings;
+
+    let unit_count = reader.varint()?;
+    let mut units = vec![];
+    for _ in 0..unit_count {
+        let name = reader.string()?;
+        let command_count = reader.varint()?;
+        let mut commands = vec![];
+        for _ in 0..command_count {
+            commands.push(reader.command()?);
+        }
+        units.push((name, ASTNode::Program { commands }));
+    }
+
+    if reader.offset != bytes.len() {
+        return Err(format!("Unexpected data at offset {}", reader.offset));
+    }
+    Ok(units)
+}
+
+fn write_command(bytes: &mut Vec<u8>, strings: &mut StringTable, command: &ASTNode) -> Result<(), String> {
+    match command {
+        ASTNode::Push { segment, index } | ASTNode::Pop { segment, index } => {
+            bytes.push(if matches!(command, ASTNode::Push { .. }) { PUSH } else { POP });
+            bytes.push(SEGMENTS.iter().position(|s| s == segment).unwrap() as u8);
+            write_varint(bytes, *index as usize);
+        }
+        ASTNode::Add => bytes.push(ADD),
+        ASTNode::Sub => bytes.push(SUB),
+        ASTNode::Neg => bytes.push(NEG),
+        ASTNode::Eq => bytes.push(EQ),
+        ASTNode::Gt => bytes.push(GT),
+        ASTNode::Lt => bytes.push(LT),
+        ASTNode::And => bytes.push(AND),
+        ASTNode::Or => bytes.push(OR),
+        ASTNode::Not => bytes.push(NOT),
+        ASTNode::Label { name } => {
+            bytes.push(LABEL);
+            write_varint(bytes, strings.index(name));
+        }
+        ASTNode::Goto { label } => {
+            bytes.push(GOTO);
+            write_varint(bytes, strings.index(label));
+        }
+        ASTNode::IfGoto { label } => {
+            bytes.push(IF_GOTO);
+            write_varint(bytes, strings.index(label));
+        }
+        ASTNode::Function { name, n_locals } => {
+            bytes.push(FUNCTION);
+            write_varint(bytes, strings.index(name));
+            write_varint(bytes, *n_locals as usize);
+        }
+        ASTNode::Call { name, n_args } => {
+            bytes.push(CALL);
+            write_varint(bytes, strings.index(name));
+            write_varint(bytes, *n_args as usize);
+        }
+        ASTNode::Return => bytes.push(RETURN),
+        ASTNode::Program { .. } => return Err("Unsupported command".to_string()),
+    }
+    Ok(())
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        bytes.push((value as u8 & 0x7F) | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+/// Names in order of first use.
+#[derive(Default)]
+struct StringTable {
+    strings: Vec<String>,
+    indices: HashMap<String, usize>,
+}
+
+impl StringTable {
+    fn index(&mut self, string: &str) -> usize {
+        if let Some(&index) = self.indices.get(string) {
+            return index;
+        }
+        self.strings.push(string.to_string());
+        self.indices.insert(string.to_string(), self.strings.len() - 1);
+        self.strings.len() - 1
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    strings: Vec<String>,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        let end = self.offset.checked_add(count).filter(|&end| end <= self.bytes.len());
+        let Some(end) = end else {
+            return Err("Unexpected end of bytecode".to_string());
+        };
+        let bytes = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn varint(&mut self) -> Result<usize, String> {
+        let offset = self.offset;
+        let mut value: usize = 0;
+        for shift in (0..usize::BITS).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7F) as usize) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(format!("Varint at offset {offset} is too long"))
+    }
+
+    fn number(&mut self) -> Result<u16, String> {
+        let offset = self.offset;
+        let value = self.varint()?;
+        u16::try_from(value).map_err(|_| format!("Number {value} at offset {offset} is out of range"))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let offset = self.offset;
+        let index = self.varint()?;
+        self.strings
+            .get(index)
+            .cloned()
+            .ok_or_else(|| format!("Invalid string index {index} at offset {offset}"))
+    }
+
+    fn command(&mut self) -> Result<ASTNode, String> {
+        let offset = self.offset;
+        Ok(match self.byte()? {
+            opcode @ (PUSH | POP) => {
+                let segment_offset = self.offset;
+                let segment = *SEGMENTS
+                    .get(self.byte()? as usize)
+                    .ok_or_else(|| format!("Invalid segment at offset {segment_offset}"))?;
+                if opcode == POP && segment == Segment::Constant {
+                    return Err(format!("Cannot pop into the constant segment at offset {offset}"));
+                }
+                let index_offset = self.offset;
+                let index = self.number()?;
+                match segment.max_index() {
+                    Some(max_index) if index > max_index => {
+                        return Err(format!(
+                            "Index {index} at offset {index_offset} is out of range for segment {segment} (0..{max_index})"
+                        ));
+                    }
+                    _ => {}
+                }
+                if opcode == PUSH {
+                    ASTNode::Push { segment, index }
+                } else {
+                    ASTNode::Pop { segment, index }
+                }
+            }
+            ADD => ASTNode::Add,
+            SUB => ASTNode::Sub,
+            NEG => ASTNode::Neg,
+            EQ => ASTNode::Eq,
+            GT => ASTNode::Gt,
+            LT => ASTNode::Lt,
+            AND => ASTNode::And,
+            OR => ASTNode::Or,
+            NOT => ASTNode::Not,
+            LABEL => ASTNode::Label { name: self.string()? },
+            GOTO => ASTNode::Goto { label: self.string()? },
+            IF_GOTO => ASTNode::IfGoto { label: self.string()? },
+            FUNCTION => ASTNode::Function { name: self.string()?, n_locals: self.number()? },
+            CALL => ASTNode::Call { name: self.string()?, n_args: self.number()? },
+            RETURN => ASTNode::Return,
+            opcode => return Err(format!("Invalid opcode 0x{opcode:02X} at offset {offset}")),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vmtrans::parser::parse_vm_code;
+
+    #[test]
+    fn test_bytecode_round_trip() {
+        let code = "function Main.main 2\npush constant 300\npop local 1\nlabel LOOP\npush local 1\nif-goto LOOP\ncall Main.main 0\nneg\ngoto LOOP\nreturn\n";
+        let units = vec![
+            ("Main".to_string(), parse_vm_code(code).unwrap()),
+            ("Empty".to_string(), ASTNode::Program { commands: vec![] }),
+        ];
+
+        let bytes = write_bytecode(&units).unwrap();
+
+        assert!(bytes.starts_with(b"HVMB\x01\x04\x04Main\x09Main.main\x04LOOP\x05Empty\x02\x00\x0A\x30\x01\x02\x01\x03\xAC\x02"));
+        assert_eq!(read_bytecode(&bytes).unwrap(), units);
+    }
+
+    #[test]
+    fn test_bytecode_errors() {
+        let bytes = write_bytecode(&[("Main".to_string(), parse_vm_code("call Main.f 1\n").unwrap())]).unwrap();
+
+        assert_eq!(read_bytecode(b"VM").unwrap_err(), "Unexpected end of bytecode");
+        assert_eq!(read_bytecode(b"HVMB\x02").unwrap_err(), "Unsupported bytecode version 2");
+        assert_eq!(read_bytecode(&bytes[..bytes.len() - 1]).unwrap_err(), "Unexpected end of bytecode");
+        let mut invalid = bytes.clone();
+        let call = invalid.iter().position(|&b| b == CALL).unwrap();
+        invalid[call] = 0x7F;
+        assert_eq!(read_bytecode(&invalid).unwrap_err(), format!("Invalid opcode 0x7F at offset {call}"));
+    }
+
+    #[test]
+    fn test_bytecode_invalid_segment_access() {
+        // Writes one command into an otherwise valid file: push/pop, segment, index
+        let encode = |opcode: u8, segment: u8, index: &[u8]| {
+            let mut bytes = b"HVMB\x01\x01\x04Main\x01\x00\x01".to_vec();
+            bytes.extend([opcode, segment]);
+            bytes.extend(index);
+            read_bytecode(&bytes)
+        };
+
+        assert_eq!(encode(POP, 3, &[1]).unwrap_err(), "Cannot pop into the constant segment at offset 14");
+        assert_eq!(
+            encode(PUSH, 7, &[8]).unwrap_err(),
+            "Index 8 at offset 16 is out of range for segment temp (0..7)"
+        );
+        assert_eq!(
+            encode(POP, 6, &[2]).unwrap_err(),
+            "Index 2 at offset 16 is out of range for segment pointer (0..1)"
+        );
+        assert_eq!(
+            encode(PUSH, 3, &[0x80, 0x80, 0x02]).unwrap_err(),
+            "Index 32768 at offset 16 is out of range for segment constant (0..32767)"
+        );
+        assert!(encode(PUSH, 3, &[0xFF, 0xFF, 0x01]).is_ok());
+        assert!(encode(POP, 6, &[1]).is_ok());
+    }
+}
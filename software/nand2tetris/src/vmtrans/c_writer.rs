@@ -0,0 +1,285 @@
+use crate::vmtrans::ast::{ASTNode, Segment};
+use crate::vmtrans::linker::allocate_statics;
+use std::collections::HashMap;
+
+const PRELUDE: &str = r#"#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+typedef int16_t word;
+
+static word ram[32768];
+
+#define SP ram[0]
+#define LCL ram[1]
+#define ARG ram[2]
+#define THIS ram[3]
+#define THAT ram[4]
+#define M(address) ram[(address) & 0x7FFF]
+#define WRAP(value) ((word)(uint16_t)(value))
+#define PUSH(value) (M(SP) = WRAP(value), SP = WRAP(SP + 1))
+#define POP() (SP = WRAP(SP - 1), M(SP))
+#define TOP M(SP - 1)
+
+static void run(void) {
+    int pc = 0;
+    word x, y, frame;
+dispatch:
+    switch (pc) {
+    case 0:"#;
+
+const EPILOGUE: &str = r#"        goto halt;
+    default:
+        fprintf(stderr, "Invalid return address %d\n", pc);
+        exit(1);
+    }
+halt:
+    return;
+}
+
+int main(int argc, char *argv[]) {
+    SP = 256;
+    run();
+    if (argc > 1) {
+        int from = atoi(argv[1]);
+        int count = argc > 2 ? atoi(argv[2]) : 1;
+        for (int address = from; address < from + count; address++) {
+            printf("RAM[%d] = %d\n", address, M(address));
+        }
+    }
+    return 0;
+}"#;
+
+/// Translates a linked VM program into a single C source file.
+///
+/// The Hack RAM is simulated by an array and the stack frames are laid out
+/// exactly as on the Hack platform. All functions live in one C function:
+/// function entries and return addresses are `case` labels of a dispatch
+/// `switch`, VM labels are plain C labels. A call to `Sys.halt` or a return
+/// from the entry point ends the program. When started with `<from> [count]`
+/// the program prints these RAM words before it exits.
+pub(crate) fn write_c_program(
+    units: &[(String, ASTNode)],
+    bootstrap: Option<&str>,
+) -> Result<String, String> {
+    let mut c_writer = CWriter::new(units);
+
+    if let Some(entry_point) = bootstrap {
+        c_writer.write_call(entry_point, 0)?;
+        c_writer.emit_code("goto halt;");
+    }
+
+    for (static_prefix, program) in units {
+        c_writer.static_prefix = static_prefix.to_string();
+        c_writer.current_scope = static_prefix.to_string();
+        c_writer.write_program(program)?;
+    }
+
+    if c_writer.next_id > i16::MAX as usize {
+        return Err("Program has too many functions and call sites for the C backend".to_string());
+    }
+
+    let mut lines = vec![PRELUDE.to_string()];
+    lines.extend(c_writer.lines);
+    lines.push(EPILOGUE.to_string());
+    Ok(lines.join("\n") + "\n")
+}
+
+struct CWriter {
+    static_prefix: String,
+    current_scope: String,
+    function_ids: HashMap<String, usize>,
+    label_ids: HashMap<(String, String), usize>,
+    static_addresses: HashMap<(String, u16), u16>,
+    next_id: usize,
+    pending_comment: Option<String>,
+    lines: Vec<String>,
+}
+
+impl CWriter {
+    fn new(units: &[(String, ASTNode)]) -> Self {
+        let mut c_writer = Self {
+            static_prefix: String::new(),
+            current_scope: String::new(),
+            function_ids: HashMap::new(),
+            label_ids: HashMap::new(),
+            static_addresses: allocate_statics(units),
+            next_id: 1,
+            pending_comment: None,
+            lines: vec![],
+        };
+        c_writer.assign_function_ids(units);
+        c_writer
+    }
+
+    fn assign_function_ids(&mut self, units: &[(String, ASTNode)]) {
+        for (_, program) in units {
+            let ASTNode::Program { commands } = program else {
+                continue;
+            };
+            for command in commands {
+                if let ASTNode::Function { name, .. } = command {
+                    self.function_ids.insert(name.clone(), self.next_id);
+                    self.next_id += 1;
+                }
+            }
+        }
+    }
+
+    fn write_program(&mut self, program: &ASTNode) -> Result<(), String> {
+        match program {
+            ASTNode::Program { commands } => {
+                for command in commands {
+                    self.write_command(command)?;
+                }
+                Ok(())
+            }
+            _ => Err("Expected Program node".to_string()),
+        }
+    }
+
+    fn write_command(&mut self, command: &ASTNode) -> Result<(), String> {
+        self.emit_comment(&command.to_command_string());
+
+        match command {
+            ASTNode::Push { segment, index } => {
+                let value = self.segment_value(segment, *index)?;
+                self.emit_code(&format!("PUSH({value});"));
+            }
+            ASTNode::Pop { segment, index } => {
+                let target = self.segment_value(segment, *index)?;
+                self.emit_code(&format!("y = POP(); {target} = y;"));
+            }
+            ASTNode::Add => self.emit_code("y = POP(); TOP = WRAP(TOP + y);"),
+            ASTNode::Sub => self.emit_code("y = POP(); TOP = WRAP(TOP - y);"),
+            ASTNode::And => self.emit_code("y = POP(); TOP = TOP & y;"),
+            ASTNode::Or => self.emit_code("y = POP(); TOP = TOP | y;"),
+            ASTNode::Eq => self.emit_code("y = POP(); TOP = TOP == y ? -1 : 0;"),
+            ASTNode::Gt => self.emit_code("y = POP(); TOP = TOP > y ? -1 : 0;"),
+            ASTNode::Lt => self.emit_code("y = POP(); TOP = TOP < y ? -1 : 0;"),
+            ASTNode::Neg => self.emit_code("TOP = WRAP(-TOP);"),
+            ASTNode::Not => self.emit_code("TOP = ~TOP;"),
+            ASTNode::Label { name } => {
+                let label = self.label(name);
+                self.emit_code(&format!("{label}:;"));
+            }
+            ASTNode::Goto { label } => {
+                let label = self.label(label);
+                self.emit_code(&format!("goto {label};"));
+            }
+            ASTNode::IfGoto { label } => {
+                let label = self.label(label);
+                self.emit_code(&format!("if (POP() != 0) goto {label};"));
+            }
+            ASTNode::Function { name, n_locals } => {
+                self.current_scope = name.clone();
+                let id = self.function_ids[name];
+                self.emit_code(&format!("case {id}:"));
+                for _ in 0..*n_locals {
+                    self.emit_code("PUSH(0);");
+                }
+            }
+            ASTNode::Call { name, n_args } => self.write_call(name, *n_args)?,
+            ASTNode::Return => self.write_return(),
+            ASTNode::Program { .. } => return Err("Unsupported command".to_string()),
+        }
+
+        Ok(())
+    }
+
+    fn write_call(&mut self, callee_name: &str, n_args: u16) -> Result<(), String> {
+        if callee_name == "Sys.halt" {
+            self.emit_code("goto halt;");
+            return Ok(());
+        }
+        let callee_id = *self
+            .function_ids
+            .get(callee_name)
+            .ok_or_else(|| format!("Call to undefined function {callee_name}"))?;
+        let return_id = self.next_id;
+        self.next_id += 1;
+
+        self.emit_code(&format!("PUSH({return_id}); PUSH(LCL); PUSH(ARG); PUSH(THIS); PUSH(THAT);"));
+        self.emit_code(&format!("ARG = WRAP(SP - {}); LCL = SP;", n_args as u32 + 5));
+        self.emit_code(&format!("pc = {callee_id}; goto dispatch;"));
+        self.emit_code(&format!("case {return_id}:"));
+        Ok(())
+    }
+
+    fn write_return(&mut self) {
+        self.emit_code("frame = LCL; x = M(frame - 5);");
+        self.emit_code("M(ARG) = POP(); SP = WRAP(ARG + 1);");
+        self.emit_code("THAT = M(frame - 1); THIS = M(frame - 2); ARG = M(frame - 3); LCL = M(frame - 4);");
+        self.emit_code("pc = x; goto dispatch;");
+    }
+
+    /// C expression for a segment entry, usable as value and as assignment target.
+    fn segment_value(&self, segment: &Segment, index: u16) -> Result<String, String> {
+        Ok(match segment {
+            Segment::Constant => index.to_string(),
+            Segment::Static => {
+                let address = self.static_addresses[&(self.static_prefix.clone(), index)];
+                format!("ram[{address}]")
+            }
+            Segment::Temp => format!("ram[{}]", 5 + index),
+            Segment::Pointer => format!("ram[{}]", 3 + index),
+            Segment::Argument => format!("M(ARG + {index})"),
+            Segment::Local => format!("M(LCL + {index})"),
+            Segment::This => format!("M(THIS + {index})"),
+            Segment::That => format!("M(THAT + {index})"),
+        })
+    }
+
+    fn label(&mut self, name: &str) -> String {
+        let key = (self.current_scope.clone(), name.to_string());
+        let next_id = self.label_ids.len();
+        let id = *self.label_ids.entry(key).or_insert(next_id);
+        format!("L{id}")
+    }
+
+    fn emit_comment(&mut self, comment: &str) {
+        self.pending_comment = Some(comment.to_string());
+    }
+
+    fn emit_code(&mut self, code: &str) {
+        let indent = if code.starts_with("case ") { "    " } else { "        " };
+        if let Some(comment) = self.pending_comment.take() {
+            self.lines.push(format!("{indent}{code} // {comment}"));
+        } else {
+            self.lines.push(format!("{indent}{code}"));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vmtrans::native_tests::{compile_and_run, SUM_PROGRAM};
+    use crate::vmtrans::parser::parse_vm_code;
+
+    #[test]
+    fn test_write_c_program() {
+        let code = "function Main.main 1\npush constant 7\npop static 3\nlabel LOOP\ngoto LOOP\ncall Main.main 0\nreturn\n";
+        let units = vec![("Main".to_string(), parse_vm_code(code).unwrap())];
+
+        let c_code = write_c_program(&units, Some("Main.main")).unwrap();
+
+        assert!(c_code.contains("        pc = 1; goto dispatch;\n    case 2:\n        goto halt;\n"));
+        assert!(c_code.contains("    case 1: // function Main.main 1\n        PUSH(0);\n"));
+        assert!(c_code.contains("        y = POP(); ram[16] = y; // pop static 3\n"));
+        assert!(c_code.contains("        L0:; // label LOOP\n        goto L0; // goto LOOP\n"));
+        assert!(c_code.contains("    case 3:\n"));
+    }
+
+    #[test]
+    fn test_compile_and_run_c_program() {
+        let units = vec![("Main".to_string(), parse_vm_code(SUM_PROGRAM).unwrap())];
+        let c_code = write_c_program(&units, Some("Main.main")).unwrap();
+
+        let Some(output) = compile_and_run("c", "prog.c", &c_code, &["16", "2"]) else {
+            return;
+        };
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "RAM[16] = 55\nRAM[17] = -1\n");
+    }
+}
@@ -0,0 +1,242 @@
+use crate::vmtrans::ast::ASTNode;
+use crate::vmtrans::json::json_string;
+use crate::vmtrans::translator::{parse_source, VmSource};
+use std::collections::{HashMap, HashSet};
+
+/// Classes of the Jack OS.
+const OS_CLASSES: [&str; 8] = [
+    "Array", "Keyboard", "Math", "Memory", "Output", "Screen", "String", "Sys",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionNode {
+    pub name: String,
+    /// Source the function is defined in, `None` if it is only called.
+    pub source: Option<String>,
+    /// Part of a call cycle, including direct self calls.
+    pub recursive: bool,
+    /// Reachable from one of the entry points.
+    pub reachable: bool,
+    /// An OS function that is called from outside the OS.
+    pub os_entry_point: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallGraph {
+    /// Defined functions in order of definition, followed by undefined callees.
+    pub functions: Vec<FunctionNode>,
+    /// Distinct caller/callee pairs in order of first occurrence.
+    pub calls: Vec<(String, String)>,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphFormat {
+    Dot,
+    Json,
+}
+
+impl CallGraph {
+    pub fn from_sources(sources: &[VmSource], entry_points: &[&str]) -> Result<Self, String> {
+        let mut functions: Vec<FunctionNode> = vec![];
+        let mut calls: Vec<(String, String)> = vec![];
+
+        for source in sources {
+            let (_, program, _) = parse_source(source)?;
+            let ASTNode::Program { commands } = program else {
+                continue;
+            };
+            let mut caller: Option<String> = None;
+            for command in commands {
+                match command {
+                    ASTNode::Function { name, .. } => {
+                        functions.push(FunctionNode {
+                            name: name.clone(),
+                            source: Some(source.name.clone()),
+                            recursive: false,
+                            reachable: false,
+                            os_entry_point: false,
+                        });
+                        caller = Some(name);
+                    }
+                    ASTNode::Call { name, .. } => {
+                        if let Some(caller) = &caller {
+                            let call = (caller.clone(), name);
+                            if !calls.contains(&call) {
+                                calls.push(call);
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        for (_, callee) in &calls {
+            if !functions.iter().any(|f| &f.name == callee) {
+                functions.push(FunctionNode {
+                    name: callee.clone(),
+                    source: None,
+                    recursive: false,
+                    reachable: false,
+                    os_entry_point: false,
+                });
+            }
+        }
+
+        let mut graph = Self { functions, calls };
+        graph.mark(entry_points);
+        Ok(graph)
+    }
+
+    fn mark(&mut self, entry_points: &[&str]) {
+        let mut callees: HashMap<&str, Vec<&str>> = HashMap::new();
+        for (caller, callee) in &self.calls {
+            callees.entry(caller).or_default().push(callee);
+        }
+
+        let reachable = reachable_from(&callees, entry_points.iter().copied());
+        let recursive: HashSet<String> = self
+            .functions
+            .iter()
+            .filter(|f| {
+                let direct_callees = callees.get(f.name.as_str()).cloned().unwrap_or_default();
+                reachable_from(&callees, direct_callees.into_iter()).contains(f.name.as_str())
+            })
+            .map(|f| f.name.clone())
+            .collect();
+        let os_entry_points: HashSet<&str> = self
+            .calls
+            .iter()
+            .filter(|(caller, callee)| !is_os_function(caller) && is_os_function(callee))
+            .map(|(_, callee)| callee.as_str())
+            .collect();
+
+        let reachable: HashSet<String> = reachable.into_iter().map(|f| f.to_string()).collect();
+        let os_entry_points: HashSet<String> = os_entry_points.into_iter().map(|f| f.to_string()).collect();
+        for function in &mut self.functions {
+            function.reachable = reachable.contains(&function.name);
+            function.recursive = recursive.contains(&function.name);
+            function.os_entry_point = os_entry_points.contains(&function.name);
+        }
+    }
+
+    pub fn export(&self, format: GraphFormat) -> String {
+        match format {
+            GraphFormat::Dot => self.to_dot(),
+            GraphFormat::Json => self.to_json(),
+        }
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut lines = vec!["digraph calls {".to_string()];
+        for function in &self.functions {
+            let mut attributes = vec![];
+            if function.os_entry_point {
+                attributes.push("shape=box");
+            }
+            if function.recursive {
+                attributes.push("color=red");
+            }
+            if function.source.is_none() {
+                attributes.push("style=dotted");
+            } else if !function.reachable {
+                attributes.push("style=dashed");
+                attributes.push("fontcolor=gray");
+            }
+            if attributes.is_empty() {
+                lines.push(format!("    \"{}\";", function.name));
+            } else {
+                lines.push(format!("    \"{}\" [{}];", function.name, attributes.join(", ")));
+            }
+        }
+        for (caller, callee) in &self.calls {
+            lines.push(format!("    \"{caller}\" -> \"{callee}\";"));
+        }
+        lines.push("}".to_string());
+        lines.join("\n") + "\n"
+    }
+
+    pub fn to_json(&self) -> String {
+        let functions: Vec<String> = self
+            .functions
+            .iter()
+            .map(|f| {
+                let source = match &f.source {
+                    Some(source) => json_string(source),
+                    None => "null".to_string(),
+                };
+                format!(
+                    "    {{\"name\": {}, \"source\": {}, \"recursive\": {}, \"reachable\": {}, \"os_entry_point\": {}}}",
+                    json_string(&f.name), source, f.recursive, f.reachable, f.os_entry_point
+                )
+            })
+            .collect();
+        let calls: Vec<String> = self
+            .calls
+            .iter()
+            .map(|(caller, callee)| {
+                format!("    {{\"caller\": {}, \"callee\": {}}}", json_string(caller), json_string(callee))
+            })
+            .collect();
+        format!(
+            "{{\n  \"functions\": [\n{}\n  ],\n  \"calls\": [\n{}\n  ]\n}}\n",
+            functions.join(",\n"),
+            calls.join(",\n")
+        )
+    }
+}
+
+fn reachable_from<'a>(
+    callees: &HashMap<&'a str, Vec<&'a str>>,
+    start: impl Iterator<Item = &'a str>,
+) -> HashSet<&'a str> {
+    let mut visited = HashSet::new();
+    let mut worklist: Vec<&str> = start.collect();
+    while let Some(function) = worklist.pop() {
+        if visited.insert(function)
+            && let Some(next) = callees.get(function)
+        {
+            worklist.extend(next);
+        }
+    }
+    visited
+}
+
+fn is_os_function(name: &str) -> bool {
+    name.split('.')
+        .next()
+        .is_some_and(|class_name| OS_CLASSES.contains(&class_name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_call_graph() {
+        let sources = vec![
+            VmSource::new("Main.vm", "function Main.main 0\ncall Main.fib 1\ncall Output.printInt 1\nreturn\nfunction Main.fib 0\ncall Main.fib 1\nreturn\nfunction Main.unused 0\nreturn\n"),
+            VmSource::new("Output.vm", "function Output.printInt 0\ncall Output.printString 1\nreturn\n"),
+        ];
+        let graph = CallGraph::from_sources(&sources, &["Main.main"]).unwrap();
+
+        let summary: Vec<(&str, bool, bool, bool, bool)> = graph
+            .functions
+            .iter()
+            .map(|f| (f.name.as_str(), f.source.is_some(), f.recursive, f.reachable, f.os_entry_point))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("Main.main", true, false, true, false),
+                ("Main.fib", true, true, true, false),
+                ("Main.unused", true, false, false, false),
+                ("Output.printInt", true, false, true, true),
+                ("Output.printString", false, false, true, false),
+            ]
+        );
+        assert_eq!(graph.calls.len(), 4);
+        assert!(graph.to_dot().contains("    \"Main.fib\" [color=red];\n    \"Main.unused\" [style=dashed, fontcolor=gray];\n"));
+        assert!(graph.to_json().contains("{\"name\": \"Output.printString\", \"source\": null, \"recursive\": false, \"reachable\": true, \"os_entry_point\": false}"));
+    }
+}
@@ -1,7 +1,328 @@
+use crate::vmtrans::bytecode::{read_bytecode, write_bytecode};
+use crate::vmtrans::call_graph::{CallGraph, GraphFormat};
+use crate::vmtrans::translator::{
+    parse_source, translate_with_source_map, HaltMode, MemoryLayout, Target, TranslateOptions, TranslateProgress,
+    VmSource,
+};
+use std::collections::HashSet;
+use std::path::Path;
+
 #[derive(clap::Parser, Debug, Clone)]
-#[command(name="VMTranslator", version, about="Translates VM code to Hack assembly", long_about = None)]
+#[command(name="VMTranslator", version, about="Translates VM code to Hack assembly, C or x86-64 assembly", long_about = None)]
 pub struct Cli {
-    pub source: String,
-    #[arg(short='s', long="no-call-sys-init", help="Suppresses the automatic call to Sys.init")]
+    #[arg(required = true, help = "VM files or directories containing VM files")]
+    pub sources: Vec<String>,
+    #[arg(short='o', long="output", help="Output file (defaults to a name derived from the first source)")]
+    pub output: Option<String>,
+    #[arg(short='s', long="no-call-sys-init", help="Suppresses the bootstrap code that calls the entry point")]
     pub no_call_sys_init: bool,
-}
\ No newline at end of file
+    #[arg(short='e', long="entry", default_value = "Sys.init", help="Function called by the bootstrap code")]
+    pub entry_point: String,
+    #[arg(long="stack-base", help="Initial value of SP (defaults to 256 with bootstrap code)")]
+    pub stack_base: Option<u16>,
+    #[arg(long="local", help="Initial value of LCL")]
+    pub local: Option<u16>,
+    #[arg(long="argument", help="Initial value of ARG")]
+    pub argument: Option<u16>,
+    #[arg(long="this", help="Initial value of THIS")]
+    pub this: Option<u16>,
+    #[arg(long="that", help="Initial value of THAT")]
+    pub that: Option<u16>,
+    #[arg(long="halt", value_enum, default_value_t = HaltMode::Loop, help="How the program ends")]
+    pub halt: HaltMode,
+    #[arg(long="allow-external", help="Accepts calls to functions defined outside the sources, such as the OS")]
+    pub allow_external: bool,
+    #[arg(long="verify-stack", help="Verifies the stack depth of every VM function before translating")]
+    pub verify_stack: bool,
+    #[arg(short='O', long="opt-level", default_value_t = 0, help="Optimization level (1: tail calls, 2: also cache the top of the stack in D)")]
+    pub opt_level: u8,
+    #[arg(long="inline", default_value_t = 0, help="Inlines leaf functions with at most this many commands (0: off)")]
+    pub inline_limit: usize,
+    #[arg(short='t', long="target", value_enum, default_value_t = Target::Hack, help="Target platform of the translation")]
+    pub target: Target,
+    #[arg(long="source-map", help="Also writes a JSON map from ROM addresses to VM commands next to the output")]
+    pub source_map: bool,
+}
+
+#[derive(clap::Parser, Debug, Clone)]
+#[command(name="VMCallGraph", version, about="Exports the call graph of VM programs", long_about = None)]
+pub struct CallGraphCli {
+    #[arg(required = true, help = "VM files or directories containing VM files")]
+    pub sources: Vec<String>,
+    #[arg(short='o', long="output", help="Output file (defaults to standard output)")]
+    pub output: Option<String>,
+    #[arg(short='f', long="format", value_enum, default_value_t = GraphFormat::Dot, help="Output format")]
+    pub format: GraphFormat,
+    #[arg(short='e', long="entry", default_value = "Sys.init", help="Entry points for the reachability analysis")]
+    pub entry_points: Vec<String>,
+}
+
+#[derive(clap::Parser, Debug, Clone)]
+#[command(name="VMBytecode", version, about="Converts VM code to and from the binary VM bytecode format", long_about = None)]
+pub struct BytecodeCli {
+    #[command(subcommand)]
+    pub command: BytecodeCommand,
+}
+
+#[derive(clap::Subcommand, Debug, Clone)]
+pub enum BytecodeCommand {
+    /// Packs VM files into one bytecode file
+    Encode {
+        #[arg(required = true, help = "VM files or directories containing VM files")]
+        sources: Vec<String>,
+        #[arg(short='o', long="output", help="Output file (defaults to a name derived from the first source)")]
+        output: Option<String>,
+    },
+    /// Unpacks a bytecode file into one VM file per unit
+    Decode {
+        #[arg(help = "Bytecode file")]
+        source: String,
+        #[arg(short='o', long="output", help="Output directory (defaults to the current directory)")]
+        output: Option<String>,
+    },
+}
+
+pub fn write_code(config: &Cli) -> Result<(), String> {
+    if config.source_map && config.target != Target::Hack {
+        return Err("Source maps are only available for the Hack target".to_string());
+    }
+    let vm_sources = read_vm_sources(&config.sources)?;
+
+    let options = TranslateOptions {
+        target: config.target,
+        bootstrap: !config.no_call_sys_init,
+        entry_point: config.entry_point.clone(),
+        memory_layout: memory_layout(config),
+        halt: config.halt,
+        allow_external: config.allow_external,
+        verify_stack: config.verify_stack,
+        optimization_level: config.opt_level,
+        inline_limit: config.inline_limit,
+    };
+    let (code, source_map) = translate_with_source_map(&vm_sources, &options, Some(&mut ConsoleProgress))?;
+
+    let output_file_name = output_file_name(&config.output, &config.sources, config.target.file_extension())?;
+
+    std::fs::write(&output_file_name, code)
+        .map_err(|e| format!("Error writing to file {output_file_name}: {e}"))?;
+    println!("Wrote output to {}", output_file_name);
+
+    if let Some(source_map) = source_map.filter(|_| config.source_map) {
+        let map_file_name = Path::new(&output_file_name).with_extension("map.json");
+        std::fs::write(&map_file_name, source_map.to_json())
+            .map_err(|e| format!("Error writing to file {}: {e}", map_file_name.display()))?;
+        println!("Wrote source map to {}", map_file_name.display());
+    }
+
+    Ok(())
+}
+
+pub fn write_call_graph(config: &CallGraphCli) -> Result<(), String> {
+    let vm_sources = read_vm_sources(&config.sources)?;
+    let entry_points: Vec<&str> = config.entry_points.iter().map(|e| e.as_str()).collect();
+    let graph = CallGraph::from_sources(&vm_sources, &entry_points)?;
+    let content = graph.export(config.format);
+
+    match &config.output {
+        Some(output) => std::fs::write(output, content)
+            .map_err(|e| format!("Error writing to file {output}: {e}")),
+        None => {
+            print!("{content}");
+            Ok(())
+        }
+    }
+}
+
+pub fn convert_bytecode(config: &BytecodeCli) -> Result<(), String> {
+    match &config.command {
+        BytecodeCommand::Encode { sources, output } => {
+            let mut units = vec![];
+            for source in read_vm_sources(sources)? {
+                let (name, program, _) = parse_source(&source)?;
+                units.push((name, program));
+            }
+            let bytes = write_bytecode(&units)?;
+
+            let output_file_name = output_file_name(output, sources, "vmb")?;
+            std::fs::write(&output_file_name, bytes)
+                .map_err(|e| format!("Error writing to file {output_file_name}: {e}"))?;
+            println!("Wrote {} units to {}", units.len(), output_file_name);
+        }
+        BytecodeCommand::Decode { source, output } => {
+            let bytes = std::fs::read(source).map_err(|e| format!("Error reading file {source}: {e}"))?;
+            let units = read_bytecode(&bytes).map_err(|e| format!("{source}: {e}"))?;
+            if let Some((name, _)) = units.iter().find(|(name, _)| !is_valid_class_name(name)) {
+                return Err(format!("{source}: invalid unit name {name:?}"));
+            }
+
+            let directory = Path::new(output.as_deref().unwrap_or("."));
+            for (name, program) in &units {
+                let file_name = directory.join(format!("{name}.vm"));
+                std::fs::write(&file_name, program.to_vm_code())
+                    .map_err(|e| format!("Error writing to file {}: {e}", file_name.display()))?;
+                println!("Wrote {}", file_name.display());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether a decoded unit name can be used as a VM file name: a letter or
+/// underscore followed by letters, digits and underscores, like a Jack class.
+fn is_valid_class_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// The given output file, or one named after the first source with the given
+/// extension. For a directory, the file is placed inside it.
+fn output_file_name(output: &Option<String>, sources: &[String], extension: &str) -> Result<String, String> {
+    if let Some(output) = output {
+        return Ok(output.clone());
+    }
+    let source = sources.first().ok_or_else(|| "No source given".to_string())?;
+    let path = Path::new(source);
+    let base_name = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .ok_or_else(|| "Invalid source name".to_string())?;
+
+    if path.is_dir() {
+        Ok(format!(
+            "{}/{}.{}",
+            path.to_str()
+                .ok_or_else(|| "Invalid directory path".to_string())?,
+            base_name,
+            extension
+        ))
+    } else {
+        Ok(format!("{}.{}", base_name, extension))
+    }
+}
+
+/// The memory layout given on the command line, if any pointer was given.
+fn memory_layout(config: &Cli) -> Option<MemoryLayout> {
+    let pointers = [config.local, config.argument, config.this, config.that];
+    if config.stack_base.is_none() && pointers.iter().all(Option::is_none) {
+        return None;
+    }
+    Some(MemoryLayout {
+        stack_base: config.stack_base.unwrap_or(MemoryLayout::default().stack_base),
+        local: config.local,
+        argument: config.argument,
+        this: config.this,
+        that: config.that,
+    })
+}
+
+fn read_vm_sources(sources: &[String]) -> Result<Vec<VmSource>, String> {
+    let mut vm_sources = vec![];
+    for file_path in collect_vm_files(sources)? {
+        let vm_code = std::fs::read_to_string(&file_path)
+            .map_err(|e| format!("Error reading file {}: {}", file_path, e))?;
+        vm_sources.push(VmSource::new(&file_path, &vm_code));
+    }
+    Ok(vm_sources)
+}
+
+struct ConsoleProgress;
+
+impl TranslateProgress for ConsoleProgress {
+    fn source_started(&mut self, name: &str) {
+        print!("Translating {name}...");
+    }
+
+    fn source_finished(&mut self, _name: &str, success: bool) {
+        println!("{}", if success { " done." } else { " failed." });
+    }
+}
+
+/// Expands the given sources into the list of `.vm` files to translate.
+/// Files are taken as given, directories contribute their `.vm` files sorted
+/// by name, so that the generated code does not depend on the file system.
+/// A file reached through several sources is only listed the first time.
+fn collect_vm_files(sources: &[String]) -> Result<Vec<String>, String> {
+    let mut candidates = vec![];
+
+    for source in sources {
+        let path = Path::new(source);
+        if !path.exists() {
+            return Err(format!("{source} does not exist"));
+        } else if path.is_file() {
+            candidates.push(source.clone());
+        } else if path.is_dir() {
+            let entries = std::fs::read_dir(path)
+                .map_err(|e| format!("Error reading directory {}: {}", source, e))?;
+            let mut dir_files = vec![];
+            for entry in entries {
+                let entry = entry.map_err(|e| format!("Error reading directory entry: {e}"))?;
+                let path = entry.path();
+                if path.is_file()
+                    && let Some(ext) = path.extension()
+                    && ext == "vm"
+                {
+                    let file_path = path
+                        .to_str()
+                        .ok_or_else(|| "Invalid file path".to_string())?;
+                    dir_files.push(file_path.to_string());
+                }
+            }
+            dir_files.sort();
+            candidates.extend(dir_files);
+        } else {
+            return Err(format!("{source} is not a file or directory"));
+        }
+    }
+
+    let mut seen = HashSet::new();
+    let mut vm_files = vec![];
+    for file_path in candidates {
+        let canonical = std::fs::canonicalize(&file_path)
+            .map_err(|e| format!("Error resolving path {}: {}", file_path, e))?;
+        if seen.insert(canonical) {
+            vm_files.push(file_path);
+        }
+    }
+
+    Ok(vm_files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collect_vm_files_sorted() {
+        let dir = std::env::temp_dir().join(format!("vmtrans-collect-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["Sys.vm", "Main.vm", "Array.vm", "notes.txt"] {
+            std::fs::write(dir.join(name), "").unwrap();
+        }
+        let dir_name = dir.to_str().unwrap().to_string();
+
+        let vm_files = collect_vm_files(&[
+            format!("{dir_name}/Sys.vm"),
+            dir_name.clone(),
+            format!("{dir_name}/../{}/Main.vm", dir.file_name().unwrap().to_str().unwrap()),
+        ]);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let vm_files = vm_files.unwrap();
+        let names: Vec<&str> = vm_files
+            .iter()
+            .map(|f| Path::new(f).file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["Sys.vm", "Array.vm", "Main.vm"]);
+    }
+
+    #[test]
+    fn test_is_valid_class_name() {
+        assert!(is_valid_class_name("Main"));
+        assert!(is_valid_class_name("_Point3D"));
+        for name in ["", "3D", "../Main", "dir/Main", "dir\\Main", "..", "Main.vm"] {
+            assert!(!is_valid_class_name(name), "{name}");
+        }
+    }
+}
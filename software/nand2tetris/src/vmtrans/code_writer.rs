@@ -1,123 +1,25 @@
 use crate::vmtrans::ast::{ASTNode, Segment, Segment::*};
-use crate::vmtrans::parser::parse_vm_code;
+use crate::vmtrans::source_map::SourceLocation;
 use std::collections::HashMap;
-use std::path::Path;
-use crate::vmtrans::Cli;
-
-pub fn write_asm_code(config: &Cli) -> Result<(), String> {
-    let source = &config.source;
-    let path = Path::new(source);
-    let base_name = path
-        .file_stem()
-        .and_then(|s| s.to_str())
-        .ok_or_else(|| "Invalid source name".to_string())?;
-
-    let mut asm_lines = if !config.no_call_sys_init {
-        translate_start_of_program()
-    } else {
-        vec![]
-    };
-
-    asm_lines.extend(if !path.exists() {
-        return Err(format!("{source} does not exist"));
-    } else if path.is_file() {
-        translate_vm_file(source)?
-    } else if path.is_dir() {
-        translate_vm_directory(source)?
-    } else {
-        return Err(format!("{source} is not a file or directory"));
-    });
-
-    if config.no_call_sys_init {
-        asm_lines.extend(translate_end_of_program(base_name));
-    }
-
-    let mut asm_file_name = format!("{}.asm", base_name);
-    if path.is_dir() {
-        asm_file_name = format!(
-            "{}/{}.asm",
-            path.to_str()
-                .ok_or_else(|| "Invalid directory path".to_string())?,
-            base_name
-        );
-    }
-
-    std::fs::write(&asm_file_name, asm_lines.join("\n") + "\n")
-        .map_err(|e| format!("Error writing to file {asm_file_name}: {e}"))?;
-    println!("Wrote output to {}", asm_file_name);
-
-    Ok(())
-}
-
-fn translate_start_of_program() -> Vec<String> {
-    let mut lines = vec![];
-    // Initialize SP to 256
-    lines.push("@256 // <--- Start".to_string());
-    lines.push("D=A".to_string());
-    lines.push("@SP".to_string());
-    lines.push("M=D".to_string());
-    // Call Sys.init
-    let mut code_writer = CodeWriter::new("Sys".to_string());
-    code_writer.write_call("Sys.init", 0);
-    lines.extend(code_writer.get_lines());
-    lines
-}
+use std::rc::Rc;
 
-fn translate_end_of_program(prefix: &str) -> Vec<String> {
-    let mut lines = vec![];
-    let end_label = format!("{prefix}.end");
-    lines.push(format!("({end_label}) // <-- end of program"));
-    lines.push(format!("@{end_label}"));
-    lines.push("0;JMP".to_string());
-    lines
-}
-
-fn translate_vm_directory(dir_path: &str) -> Result<Vec<String>, String> {
-    let mut all_asm_lines = vec![];
-    let entries = std::fs::read_dir(dir_path)
-        .map_err(|e| format!("Error reading directory {}: {}", dir_path, e))?;
-
-    for entry in entries {
-        let entry = entry.map_err(|e| format!("Error reading directory entry: {e}"))?;
-        let path = entry.path();
-        if path.is_file() {
-            if let Some(ext) = path.extension() {
-                if ext == "vm" {
-                    let file_path = path
-                        .to_str()
-                        .ok_or_else(|| "Invalid file path".to_string())?;
-                    let asm_lines = translate_vm_file(file_path)?;
-                    all_asm_lines.extend(asm_lines);
-                }
-            }
-        }
-    }
-
-    Ok(all_asm_lines)
-}
+/// Former home of the command line translation, kept so that existing callers
+/// of `code_writer::write_asm_code` still compile. Use `vmtrans::write_code`.
+pub use crate::vmtrans::cli::write_code as write_asm_code;
 
-fn translate_vm_file(file_path: &str) -> Result<Vec<String>, String> {
-    let vm_code = std::fs::read_to_string(file_path)
-        .map_err(|e| format!("Error reading file {}: {}", file_path, e))?;
-    let static_prefix = Path::new(file_path)
-        .file_stem()
-        .and_then(|s| s.to_str())
-        .ok_or_else(|| format!("Invalid file name: {}", file_path))?;
-
-    print!("Translating {file_path}...");
-    let program = parse_vm_code(&vm_code)?;
-    let mut code_writer = CodeWriter::new(static_prefix.to_string());
-    let asm_lines = code_writer.write_program(&program)?;
-    println!(" done.");
-    Ok(asm_lines)
-}
-
-struct CodeWriter {
+pub(crate) struct CodeWriter {
     static_prefix: String,
     current_function: Option<String>,
     label_counters: HashMap<String, u16>,
+    tail_calls: bool,
+    cache_top: bool,
+    top_in_d: bool,
     pending_comment: Option<String>,
     lines: Vec<String>,
+    /// Source file and line of every command of the program, if known.
+    source: Option<(String, Vec<usize>)>,
+    current_location: Option<Rc<SourceLocation>>,
+    line_locations: Vec<Option<Rc<SourceLocation>>>,
 }
 
 impl CodeWriter {
@@ -126,29 +28,88 @@ impl CodeWriter {
             static_prefix,
             current_function: None,
             label_counters: HashMap::new(),
+            tail_calls: false,
+            cache_top: false,
+            top_in_d: false,
             pending_comment: None,
             lines: vec![],
+            source: None,
+            current_location: None,
+            line_locations: vec![],
         }
     }
 
+    /// Translates `call F n` directly followed by `return` into a jump that
+    /// reuses the frame of the current function.
+    pub fn set_tail_calls(&mut self, tail_calls: bool) {
+        self.tail_calls = tail_calls;
+    }
+
+    /// Keeps the top of the VM stack in D between commands where possible,
+    /// instead of writing every intermediate result back to the stack.
+    pub fn set_cache_top(&mut self, cache_top: bool) {
+        self.cache_top = cache_top;
+    }
+
+    /// Names the source of the program and the line of each of its commands,
+    /// so every emitted line can be traced back to its VM command.
+    pub fn set_source(&mut self, file: String, lines: Vec<usize>) {
+        self.source = Some((file, lines));
+    }
+
     pub fn get_lines(&self) -> Vec<String> {
         self.lines.clone()
     }
 
+    /// Location of the VM command each line of `get_lines` was emitted for.
+    pub fn get_line_locations(&self) -> Vec<Option<Rc<SourceLocation>>> {
+        self.line_locations.clone()
+    }
+
     pub fn write_program(&mut self, program: &ASTNode) -> Result<Vec<String>, String> {
         match program {
             ASTNode::Program { commands } => {
-                for command in commands {
-                    self.write_command(command)?;
+                let mut commands = commands.iter().enumerate().peekable();
+                while let Some((index, command)) = commands.next() {
+                    self.set_location(index, command);
+                    match (command, commands.peek().map(|(_, next)| next)) {
+                        (ASTNode::Call { name, n_args }, Some(ASTNode::Return)) if self.tail_calls => {
+                            self.emit_comment(&format!("{} (tail call)", command.to_command_string()));
+                            self.flush_top();
+                            self.write_tail_call(name, *n_args);
+                            commands.next();
+                        }
+                        _ => self.write_command(command)?,
+                    }
                 }
+                self.flush_top();
                 Ok(self.get_lines())
             }
             _ => Err("Expected Program node".to_string()),
         }
     }
 
+    fn set_location(&mut self, index: usize, command: &ASTNode) {
+        let Some((file, lines)) = &self.source else {
+            return;
+        };
+        let function = match command {
+            ASTNode::Function { name, .. } => Some(name.clone()),
+            _ => self.current_function.clone(),
+        };
+        self.current_location = Some(Rc::new(SourceLocation {
+            file: file.clone(),
+            line: lines[index],
+            function,
+            command: command.to_command_string(),
+        }));
+    }
+
     fn write_command(&mut self, command: &ASTNode) -> Result<(), String> {
         self.emit_comment(&command.to_command_string());
+        if self.cache_top {
+            return self.write_command_cached(command);
+        }
 
         match command {
             ASTNode::Push { segment, index } => self.write_push(segment, *index),
@@ -168,6 +129,104 @@ impl CodeWriter {
         Ok(())
     }
 
+    /// Translates a command with the top of the stack possibly held in D.
+    ///
+    /// Between commands the top of the stack is either in memory like in the
+    /// plain translation (`top_in_d == false`), or it is in D and SP points to
+    /// the slot it belongs to. Labels, jumps, calls and returns always see the
+    /// stack in memory, so all paths agree at every join point.
+    fn write_command_cached(&mut self, command: &ASTNode) -> Result<(), String> {
+        match command {
+            ASTNode::Push { segment, index } => {
+                self.flush_top();
+                self.write_segment_to_d(segment, *index);
+                self.top_in_d = true;
+            }
+            ASTNode::Pop { segment, index } => {
+                self.load_top();
+                self.write_d_to_segment(segment, *index);
+            }
+            ASTNode::Add | ASTNode::Sub | ASTNode::And | ASTNode::Or => {
+                self.load_top(); // D = y
+                self.emit_code("@SP");
+                self.emit_code("AM=M-1");
+                match command {
+                    ASTNode::Add => self.emit_code("D=D+M"), // x + y
+                    ASTNode::Sub => self.emit_code("D=M-D"), // x - y
+                    ASTNode::And => self.emit_code("D=D&M"), // x & y
+                    _ => self.emit_code("D=D|M"),            // x | y
+                }
+                self.top_in_d = true;
+            }
+            ASTNode::Neg | ASTNode::Not => {
+                self.load_top();
+                match command {
+                    ASTNode::Neg => self.emit_code("D=-D"),
+                    _ => self.emit_code("D=!D"),
+                }
+                self.top_in_d = true;
+            }
+            ASTNode::Eq | ASTNode::Lt | ASTNode::Gt => {
+                self.load_top(); // D = y
+                self.emit_code("@SP");
+                self.emit_code("AM=M-1");
+                self.emit_code("D=M-D"); // D = x - y
+                let true_label = self.create_unique_label("true");
+                let end_label = self.create_unique_label("end");
+                self.emit_code(&format!("@{true_label}"));
+                match command {
+                    ASTNode::Eq => self.emit_code("D;JEQ"),
+                    ASTNode::Lt => self.emit_code("D;JLT"),
+                    _ => self.emit_code("D;JGT"),
+                }
+                self.emit_code("D=0");
+                self.emit_code(&format!("@{end_label}"));
+                self.emit_code("0;JMP");
+                self.emit_code(&format!("({true_label})"));
+                self.emit_code("D=-1");
+                self.emit_code(&format!("({end_label})"));
+                self.top_in_d = true;
+            }
+            ASTNode::IfGoto { label } => {
+                self.load_top();
+                let label = self.create_label(label);
+                self.emit_code(&format!("@{label}"));
+                self.emit_code("D;JNE");
+            }
+            _ => {
+                self.flush_top();
+                self.cache_top = false;
+                let result = self.write_command(command);
+                self.cache_top = true;
+                result?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes a top of the stack held in D back to the stack.
+    fn flush_top(&mut self) {
+        if self.top_in_d {
+            self.emit_code("@SP");
+            self.emit_code("M=M+1");
+            self.emit_code("A=M-1");
+            self.emit_code("M=D");
+            self.top_in_d = false;
+        }
+    }
+
+    /// Moves the top of the stack into D and removes it from the stack.
+    fn load_top(&mut self) {
+        if self.top_in_d {
+            self.top_in_d = false;
+        } else {
+            self.emit_code("@SP");
+            self.emit_code("AM=M-1");
+            self.emit_code("D=M");
+        }
+    }
+
     fn write_return(&mut self)  {
         // FRAME = LCL
         self.emit_code("@LCL");
@@ -223,7 +282,7 @@ impl CodeWriter {
         self.emit_code("0;JMP");
     }
 
-    fn write_call(&mut self, callee_name: &str, n_args: u16)  {
+    pub fn write_call(&mut self, callee_name: &str, n_args: u16)  {
         let return_label = self.create_unique_label("ret");
         // Push return address
         self.emit_code(&format!("@{}", return_label));
@@ -261,6 +320,58 @@ impl CodeWriter {
         self.emit_code(&format!("({return_label})"));
     }
 
+    fn write_tail_call(&mut self, callee_name: &str, n_args: u16) {
+        // The saved frame of the current function (return address, LCL, ARG,
+        // THIS, THAT of its caller) becomes the saved frame of the callee. The
+        // number of arguments of the current function is only known at runtime,
+        // so the frame is parked above the stack while the arguments move down.
+        // Parking copies to higher addresses, but above SP, where it cannot
+        // meet its source below LCL. The two other copies go to lower or equal
+        // addresses word by word from the start, hence they cannot overwrite
+        // a word before they have read it.
+        // Park saved frame at SP..SP+4
+        self.emit_code("@LCL");
+        self.emit_code("D=M");
+        self.emit_code("@5");
+        self.emit_code("D=D-A");
+        self.emit_code("@R13");
+        self.emit_code("M=D");
+        self.move_content("SP", "R14");
+        self.copy_words(5);
+        // Move the arguments to ARG..ARG+n-1
+        self.emit_code("@SP");
+        self.emit_code("D=M");
+        self.emit_code(&format!("@{n_args}"));
+        self.emit_code("D=D-A");
+        self.emit_code("@R13");
+        self.emit_code("M=D");
+        self.move_content("ARG", "R14");
+        self.copy_words(n_args);
+        // Move the parked frame to ARG+n..ARG+n+4
+        self.move_content("SP", "R13");
+        self.copy_words(5);
+        // LCL = SP = ARG + n + 5
+        self.move_content("R14", "LCL");
+        self.move_content("R14", "SP");
+        // Transfer control to callee
+        self.emit_code(&format!("@{callee_name}"));
+        self.emit_code("0;JMP");
+    }
+
+    /// Copies `count` words from `*R13` to `*R14`, advancing both pointers.
+    fn copy_words(&mut self, count: u16) {
+        for _ in 0..count {
+            self.emit_code("@R13");
+            self.emit_code("AM=M+1");
+            self.emit_code("A=A-1");
+            self.emit_code("D=M");
+            self.emit_code("@R14");
+            self.emit_code("AM=M+1");
+            self.emit_code("A=A-1");
+            self.emit_code("M=D");
+        }
+    }
+
     fn move_content(&mut self, from: &str, to: &str) {
         self.emit_code(&format!("@{}", from));
         self.emit_code("D=M");
@@ -549,5 +660,53 @@ impl CodeWriter {
         } else {
             self.lines.push(code.to_string());
         }
+        self.line_locations.push(self.current_location.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COPY_WORD: [&str; 8] = ["@R13", "AM=M+1", "A=A-1", "D=M", "@R14", "AM=M+1", "A=A-1", "M=D"];
+
+    /// The full sequence `write_tail_call` emits for `n_args` arguments.
+    fn tail_call_sequence(n_args: u16) -> Vec<String> {
+        let push_all = |expected: &mut Vec<String>, lines: &[&str]| {
+            expected.extend(lines.iter().map(|line| line.to_string()));
+        };
+        let mut expected = vec![];
+        // Park the frame at SP..SP+4
+        push_all(&mut expected, &["@LCL", "D=M", "@5", "D=D-A", "@R13", "M=D", "@SP", "D=M", "@R14", "M=D"]);
+        push_all(&mut expected, &COPY_WORD.repeat(5));
+        // Move the arguments to ARG
+        push_all(&mut expected, &["@SP", "D=M", &format!("@{n_args}"), "D=D-A", "@R13", "M=D"]);
+        push_all(&mut expected, &["@ARG", "D=M", "@R14", "M=D"]);
+        push_all(&mut expected, &COPY_WORD.repeat(n_args as usize));
+        // Move the parked frame behind them
+        push_all(&mut expected, &["@SP", "D=M", "@R13", "M=D"]);
+        push_all(&mut expected, &COPY_WORD.repeat(5));
+        push_all(&mut expected, &["@R14", "D=M", "@LCL", "M=D", "@R14", "D=M", "@SP", "M=D", "@Main.f", "0;JMP"]);
+        expected
+    }
+
+    #[test]
+    fn test_tail_call_without_arguments() {
+        let mut code_writer = CodeWriter::new("Main".to_string());
+        code_writer.write_tail_call("Main.f", 0);
+
+        let lines = code_writer.get_lines();
+        assert_eq!(lines.len(), 10 + 40 + 10 + 4 + 40 + 10);
+        assert_eq!(lines, tail_call_sequence(0));
+    }
+
+    #[test]
+    fn test_tail_call_with_more_arguments_than_frame_words() {
+        let mut code_writer = CodeWriter::new("Main".to_string());
+        code_writer.write_tail_call("Main.f", 7);
+
+        let lines = code_writer.get_lines();
+        assert_eq!(lines.len(), 10 + 40 + 10 + 56 + 4 + 40 + 10);
+        assert_eq!(lines, tail_call_sequence(7));
     }
 }
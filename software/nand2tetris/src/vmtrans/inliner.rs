@@ -0,0 +1,348 @@
+use crate::vmtrans::ast::{ASTNode, Segment};
+use crate::vmtrans::stack_checker::stack_depths;
+use std::collections::{HashMap, HashSet};
+
+/// Number of slots of the temp segment.
+const TEMP_SLOTS: u16 = 8;
+
+/// A leaf function that can be copied into its callers.
+struct InlineCandidate {
+    unit_name: String,
+    n_locals: u16,
+    /// Number of arguments the body reads or writes.
+    n_used_args: u16,
+    uses_static: bool,
+    /// Pointer segment entries the body writes, restored after the inlined body.
+    written_pointers: Vec<u16>,
+    body: Vec<ASTNode>,
+}
+
+/// Replaces calls to small leaf functions by a copy of their body.
+///
+/// A function is inlined if it has at most `max_commands` commands, calls no
+/// other function, does not use the temp segment and returns with exactly one
+/// value on its stack. The arguments and locals of the copy live in the temp
+/// segment, so calls are only inlined into functions that do not use temp
+/// themselves. `this` and `that` are preserved by saving the pointer entries
+/// the body writes. Functions with statics are only inlined into their own
+/// unit. The definitions themselves are kept.
+///
+/// Also returns, for every command of the result, the index of the command of
+/// the original unit it stems from; inlined commands stem from their call.
+pub(crate) fn inline_functions(
+    units: &[(String, ASTNode)],
+    max_commands: usize,
+    excluded_functions: &[&str],
+) -> (Vec<(String, ASTNode)>, Vec<Vec<usize>>) {
+    let candidates = find_candidates(units, max_commands, excluded_functions);
+    let temp_users = find_temp_users(units);
+    let mut inline_count = 0;
+    let mut inlined_units = vec![];
+    let mut origins = vec![];
+
+    for (unit_name, program) in units {
+        let ASTNode::Program { commands } = program else {
+            inlined_units.push((unit_name.clone(), program.clone()));
+            origins.push(vec![]);
+            continue;
+        };
+        let mut inlined = vec![];
+        let mut unit_origins = vec![];
+        let mut caller_uses_temp = true;
+        for (index, command) in commands.iter().enumerate() {
+            if let ASTNode::Function { name, .. } = command {
+                caller_uses_temp = temp_users.contains(name.as_str());
+            }
+            if let ASTNode::Call { name, n_args } = command
+                && !caller_uses_temp
+                && let Some(candidate) = candidates.get(name.as_str())
+                && can_inline_at(candidate, unit_name, *n_args)
+            {
+                inline_call(name, candidate, *n_args, inline_count, &mut inlined);
+                inline_count += 1;
+            } else {
+                inlined.push(command.clone());
+            }
+            unit_origins.resize(inlined.len(), index);
+        }
+        inlined_units.push((unit_name.clone(), ASTNode::Program { commands: inlined }));
+        origins.push(unit_origins);
+    }
+
+    (inlined_units, origins)
+}
+
+/// Names of the functions that read or write the temp segment.
+fn find_temp_users(units: &[(String, ASTNode)]) -> HashSet<&str> {
+    let mut temp_users = HashSet::new();
+
+    for (_, program) in units {
+        let ASTNode::Program { commands } = program else {
+            continue;
+        };
+        let mut current_function = None;
+        for command in commands {
+            match command {
+                ASTNode::Function { name, .. } => current_function = Some(name.as_str()),
+                ASTNode::Push { segment: Segment::Temp, .. } | ASTNode::Pop { segment: Segment::Temp, .. } => {
+                    if let Some(name) = current_function {
+                        temp_users.insert(name);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    temp_users
+}
+
+fn find_candidates<'a>(
+    units: &'a [(String, ASTNode)],
+    max_commands: usize,
+    excluded_functions: &[&str],
+) -> HashMap<&'a str, InlineCandidate> {
+    let mut candidates = HashMap::new();
+
+    for (unit_name, program) in units {
+        let ASTNode::Program { commands } = program else {
+            continue;
+        };
+        let starts: Vec<usize> = commands
+            .iter()
+            .enumerate()
+            .filter(|(_, command)| matches!(command, ASTNode::Function { .. }))
+            .map(|(i, _)| i)
+            .collect();
+        for (i, &start) in starts.iter().enumerate() {
+            let end = starts.get(i + 1).copied().unwrap_or(commands.len());
+            let function = &commands[start..end];
+            let ASTNode::Function { name, n_locals } = &function[0] else {
+                continue;
+            };
+            if excluded_functions.contains(&name.as_str()) {
+                continue;
+            }
+            if let Some(candidate) = candidate(unit_name, function, *n_locals, max_commands) {
+                candidates.insert(name.as_str(), candidate);
+            }
+        }
+    }
+
+    candidates
+}
+
+/// Checks whether a function qualifies for inlining; `function[0]` is its
+/// `function` command.
+fn candidate(unit_name: &str, function: &[ASTNode], n_locals: u16, max_commands: usize) -> Option<InlineCandidate> {
+    let body = &function[1..];
+    if body.is_empty() || body.len() > max_commands {
+        return None;
+    }
+    // Without a final return or goto the function would fall through into the next one
+    if !matches!(body.last(), Some(ASTNode::Return | ASTNode::Goto { .. })) {
+        return None;
+    }
+
+    let mut n_used_args = 0;
+    let mut uses_static = false;
+    let mut written_pointers = vec![];
+    for command in body {
+        match command {
+            ASTNode::Call { .. } => return None,
+            ASTNode::Push { segment, index } | ASTNode::Pop { segment, index } => match segment {
+                Segment::Temp => return None,
+                Segment::Argument => n_used_args = n_used_args.max(index + 1),
+                Segment::Static => uses_static = true,
+                Segment::Pointer
+                    if matches!(command, ASTNode::Pop { .. }) && !written_pointers.contains(index) =>
+                {
+                    written_pointers.push(*index);
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+    if n_used_args + n_locals + written_pointers.len() as u16 > TEMP_SLOTS {
+        return None;
+    }
+
+    let mut errors = vec![];
+    let depths = stack_depths(function, &mut errors);
+    let returns_one_value = body
+        .iter()
+        .zip(&depths)
+        .all(|(command, depth)| !matches!(command, ASTNode::Return) || matches!(depth, None | Some(1)));
+    if !errors.is_empty() || !returns_one_value {
+        return None;
+    }
+
+    Some(InlineCandidate {
+        unit_name: unit_name.to_string(),
+        n_locals,
+        n_used_args,
+        uses_static,
+        written_pointers,
+        body: body.to_vec(),
+    })
+}
+
+fn can_inline_at(candidate: &InlineCandidate, unit_name: &str, n_args: u16) -> bool {
+    (!candidate.uses_static || candidate.unit_name == unit_name)
+        && n_args >= candidate.n_used_args
+        && n_args + candidate.n_locals + candidate.written_pointers.len() as u16 <= TEMP_SLOTS
+}
+
+/// Appends the body of `name` with its arguments, locals and labels rewritten.
+///
+/// The temp segment holds the arguments first, then the locals, then the saved
+/// pointer entries.
+fn inline_call(name: &str, candidate: &InlineCandidate, n_args: u16, inline_id: usize, commands: &mut Vec<ASTNode>) {
+    let local_base = n_args;
+    let pointer_base = local_base + candidate.n_locals;
+    let rename = |label: &str| format!("{name}.inline.{inline_id}.{label}");
+    let end_label = format!("{name}.inline.{inline_id}:end");
+
+    for i in (0..n_args).rev() {
+        commands.push(ASTNode::Pop { segment: Segment::Temp, index: i });
+    }
+    for i in 0..candidate.n_locals {
+        commands.push(ASTNode::Push { segment: Segment::Constant, index: 0 });
+        commands.push(ASTNode::Pop { segment: Segment::Temp, index: local_base + i });
+    }
+    for (i, &pointer) in candidate.written_pointers.iter().enumerate() {
+        commands.push(ASTNode::Push { segment: Segment::Pointer, index: pointer });
+        commands.push(ASTNode::Pop { segment: Segment::Temp, index: pointer_base + i as u16 });
+    }
+
+    let mut needs_end_label = false;
+    let last = candidate.body.len() - 1;
+    for (i, command) in candidate.body.iter().enumerate() {
+        commands.push(match command {
+            ASTNode::Push { segment: Segment::Argument, index } => {
+                ASTNode::Push { segment: Segment::Temp, index: *index }
+            }
+            ASTNode::Pop { segment: Segment::Argument, index } => {
+                ASTNode::Pop { segment: Segment::Temp, index: *index }
+            }
+            ASTNode::Push { segment: Segment::Local, index } => {
+                ASTNode::Push { segment: Segment::Temp, index: local_base + index }
+            }
+            ASTNode::Pop { segment: Segment::Local, index } => {
+                ASTNode::Pop { segment: Segment::Temp, index: local_base + index }
+            }
+            ASTNode::Label { name } => ASTNode::Label { name: rename(name) },
+            ASTNode::Goto { label } => ASTNode::Goto { label: rename(label) },
+            ASTNode::IfGoto { label } => ASTNode::IfGoto { label: rename(label) },
+            ASTNode::Return if i == last => continue,
+            ASTNode::Return => {
+                needs_end_label = true;
+                ASTNode::Goto { label: end_label.clone() }
+            }
+            _ => command.clone(),
+        });
+    }
+    if needs_end_label {
+        commands.push(ASTNode::Label { name: end_label });
+    }
+
+    for (i, &pointer) in candidate.written_pointers.iter().enumerate() {
+        commands.push(ASTNode::Push { segment: Segment::Temp, index: pointer_base + i as u16 });
+        commands.push(ASTNode::Pop { segment: Segment::Pointer, index: pointer });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vmtrans::parser::parse_vm_code;
+
+    fn commands(program: &ASTNode) -> Vec<String> {
+        let ASTNode::Program { commands } = program else {
+            panic!("Expected Program node");
+        };
+        commands.iter().map(|command| command.to_command_string()).collect()
+    }
+
+    #[test]
+    fn test_inline_getter() {
+        let point = "function Point.getX 0\npush argument 0\npop pointer 0\npush this 0\nreturn\n";
+        let main = "function Main.main 1\npush local 0\ncall Point.getX 1\ncall Main.main 0\nreturn\n";
+        let units = vec![
+            ("Point".to_string(), parse_vm_code(point).unwrap()),
+            ("Main".to_string(), parse_vm_code(main).unwrap()),
+        ];
+
+        let (inlined, origins) = inline_functions(&units, 10, &[]);
+
+        assert_eq!(commands(&inlined[0].1), commands(&units[0].1));
+        assert_eq!(origins[1], vec![0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 3, 4]);
+        assert_eq!(
+            commands(&inlined[1].1),
+            vec![
+                "function Main.main 1",
+                "push local 0",
+                "pop temp 0",
+                "push pointer 0",
+                "pop temp 1",
+                "push temp 0",
+                "pop pointer 0",
+                "push this 0",
+                "push temp 1",
+                "pop pointer 0",
+                "call Main.main 0",
+                "return",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_inline_with_labels_and_locals() {
+        let code = "function Main.abs 1\npush argument 0\npush constant 0\nlt\nif-goto NEG\npush argument 0\nreturn\nlabel NEG\npush argument 0\nneg\nreturn\nfunction Main.main 0\npush constant 5\ncall Main.abs 1\nreturn\n";
+        let units = vec![("Main".to_string(), parse_vm_code(code).unwrap())];
+
+        let (inlined, _) = inline_functions(&units, 10, &[]);
+
+        let main: Vec<String> = commands(&inlined[0].1).into_iter().skip(11).collect();
+        assert_eq!(
+            main,
+            vec![
+                "function Main.main 0",
+                "push constant 5",
+                "pop temp 0",
+                "push constant 0",
+                "pop temp 1",
+                "push temp 0",
+                "push constant 0",
+                "lt",
+                "if-goto Main.abs.inline.0.NEG",
+                "push temp 0",
+                "goto Main.abs.inline.0:end",
+                "label Main.abs.inline.0.NEG",
+                "push temp 0",
+                "neg",
+                "label Main.abs.inline.0:end",
+                "return",
+            ]
+        );
+        // The renamed labels have to be valid VM names
+        assert!(parse_vm_code(&inlined[0].1.to_vm_code()).is_ok());
+        assert_eq!(commands(&inline_functions(&units, 9, &[]).0[0].1), commands(&units[0].1));
+        assert_eq!(commands(&inline_functions(&units, 10, &["Main.abs"]).0[0].1), commands(&units[0].1));
+    }
+
+    #[test]
+    fn test_no_inlining_into_temp_users() {
+        let code = "function Main.inc 0\npush argument 0\npush constant 1\nadd\nreturn\nfunction Main.main 0\npush constant 5\npop temp 0\npush constant 1\ncall Main.inc 1\npush temp 0\nadd\nreturn\nfunction Main.other 0\npush constant 1\ncall Main.inc 1\nreturn\n";
+        let units = vec![("Main".to_string(), parse_vm_code(code).unwrap())];
+
+        let (inlined, _) = inline_functions(&units, 10, &[]);
+
+        // Main.main keeps its call, Main.other gets the copy
+        let inlined = commands(&inlined[0].1);
+        let other = inlined.iter().position(|command| command == "function Main.other 0").unwrap();
+        assert!(inlined[..other].contains(&"call Main.inc 1".to_string()));
+        assert!(!inlined[other..].contains(&"call Main.inc 1".to_string()));
+    }
+}
@@ -0,0 +1,28 @@
+/// Quotes a string as a JSON string literal.
+pub(crate) fn json_string(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_string() {
+        assert_eq!(json_string("Main.main"), "\"Main.main\"");
+        assert_eq!(json_string("dir\\\"a\"\n\t\u{1}"), "\"dir\\\\\\\"a\\\"\\n\\t\\u0001\"");
+    }
+}
@@ -75,8 +75,8 @@ impl <'a> Lexer<'a> {
 
     fn handle_unexpected_char(&mut self, ch: char) -> VmToken {
         let error_token = VmToken::new(
-            TokenType::Error(format!("Unexpected character '{}' at line {}", ch, self.line)),
-            "".to_string(),
+            TokenType::Error(format!("Unexpected character '{}'", ch)),
+            ch.to_string(),
             self.line,
             self.column,
         );
@@ -105,8 +105,8 @@ impl Stream<VmToken> for Lexer<'_> {
                 ch => {
                     return if ch.is_ascii_alphabetic() {
                         Some(self.handle_chars(&|c: char| !c.is_whitespace()))
-                    } else if ch.is_digit(10) {
-                        Some(self.handle_chars(&|c: char| c.is_digit(10)))
+                    } else if ch.is_ascii_digit() {
+                        Some(self.handle_chars(&|c: char| c.is_ascii_digit()))
                     } else {
                         Some(self.handle_unexpected_char(ch))
                     }
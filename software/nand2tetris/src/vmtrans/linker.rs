@@ -0,0 +1,200 @@
+use crate::vmtrans::ast::{ASTNode, Segment};
+use std::collections::{HashMap, HashSet};
+
+/// Number of RAM words between 16 and 255 that hold the static variables of all files.
+const STATIC_SLOTS: usize = 240;
+
+/// Verifies a whole VM program before any code is emitted.
+///
+/// `units` holds the parsed programs together with the name of the file they
+/// came from. Every `call` (and every entry point) must target a function that
+/// is defined exactly once or provided by the target as `external_functions`, and every `goto`/`if-goto` must refer to a label
+/// of the same function. With `allow_external_calls`, calls may also target
+/// functions defined elsewhere, such as the OS of a single translated file.
+/// The static variables of all units together have to fit into the static
+/// area. All problems are collected and returned together.
+pub fn check_program(
+    units: &[(String, ASTNode)],
+    entry_points: &[&str],
+    external_functions: &[&str],
+    allow_external_calls: bool,
+) -> Result<(), Vec<String>> {
+    let mut errors = vec![];
+    let mut definitions: HashMap<&str, &str> = HashMap::new();
+    let mut calls: Vec<(&str, &str, &str)> = vec![];
+    let mut static_slots = 0;
+
+    for (unit_name, program) in units {
+        let commands = match program {
+            ASTNode::Program { commands } => commands,
+            _ => {
+                errors.push(format!("{unit_name}: expected Program node"));
+                continue;
+            }
+        };
+
+        let mut scope = LabelScope::new(unit_name, unit_name);
+        let mut static_indices = HashSet::new();
+
+        for command in commands {
+            match command {
+                ASTNode::Function { name, .. } => {
+                    scope.check(&mut errors);
+                    scope = LabelScope::new(unit_name, name);
+                    if let Some(first_unit) = definitions.get(name.as_str()) {
+                        errors.push(format!(
+                            "{unit_name}: function {name} is already defined in {first_unit}"
+                        ));
+                    } else {
+                        definitions.insert(name, unit_name);
+                    }
+                }
+                ASTNode::Call { name, .. } => calls.push((unit_name, scope.name, name)),
+                ASTNode::Label { name } => {
+                    scope.labels.insert(name);
+                }
+                ASTNode::Goto { label } | ASTNode::IfGoto { label } => scope.jumps.push(label),
+                ASTNode::Push { segment: Segment::Static, index }
+                | ASTNode::Pop { segment: Segment::Static, index } => {
+                    static_indices.insert(*index);
+                }
+                _ => {}
+            }
+        }
+
+        scope.check(&mut errors);
+        static_slots += static_indices.len();
+    }
+
+    if static_slots > STATIC_SLOTS {
+        errors.push(format!(
+            "program uses {static_slots} static variables, but only {STATIC_SLOTS} fit between RAM 16 and 255"
+        ));
+    }
+
+    for entry_point in entry_points {
+        if !definitions.contains_key(entry_point) {
+            errors.push(format!("entry point {entry_point} is not defined"));
+        }
+    }
+
+    for (unit_name, caller, callee) in calls {
+        if !allow_external_calls && !definitions.contains_key(callee) && !external_functions.contains(&callee) {
+            errors.push(format!(
+                "{unit_name}: {caller} calls undefined function {callee}"
+            ));
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Assigns RAM addresses to the static variables of all units, from 16 on in
+/// order of appearance, as the Hack assembler allocates them.
+pub fn allocate_statics(units: &[(String, ASTNode)]) -> HashMap<(String, u16), u16> {
+    let mut addresses = HashMap::new();
+    for (unit_name, program) in units {
+        let ASTNode::Program { commands } = program else {
+            continue;
+        };
+        for command in commands {
+            if let ASTNode::Push { segment: Segment::Static, index }
+            | ASTNode::Pop { segment: Segment::Static, index } = command
+            {
+                let next_address = 16 + addresses.len() as u16;
+                addresses.entry((unit_name.clone(), *index)).or_insert(next_address);
+            }
+        }
+    }
+    addresses
+}
+
+struct LabelScope<'a> {
+    unit_name: &'a str,
+    name: &'a str,
+    labels: HashSet<&'a str>,
+    jumps: Vec<&'a str>,
+}
+
+impl<'a> LabelScope<'a> {
+    fn new(unit_name: &'a str, name: &'a str) -> Self {
+        Self {
+            unit_name,
+            name,
+            labels: HashSet::new(),
+            jumps: vec![],
+        }
+    }
+
+    fn check(&self, errors: &mut Vec<String>) {
+        for jump in &self.jumps {
+            if !self.labels.contains(jump) {
+                errors.push(format!(
+                    "{}: {} jumps to undefined label {}",
+                    self.unit_name, self.name, jump
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vmtrans::parser::parse_vm_code;
+
+    fn unit(name: &str, code: &str) -> (String, ASTNode) {
+        (name.to_string(), parse_vm_code(code).unwrap())
+    }
+
+    #[test]
+    fn test_check_program_ok() {
+        let units = vec![
+            unit("Main", "function Main.main 0\ncall Math.abs 1\nlabel LOOP\ngoto LOOP\n"),
+            unit("Math", "function Math.abs 0\npush argument 0\nif-goto END\nlabel END\nreturn\n"),
+        ];
+        assert!(check_program(&units, &["Main.main"], &[], false).is_ok());
+    }
+
+    #[test]
+    fn test_check_program_external_functions() {
+        let units = vec![unit("Main", "function Main.main 0\ncall Math.multiply 2\nreturn\n")];
+        assert!(check_program(&units, &["Main.main"], &[], false).is_err());
+        assert!(check_program(&units, &["Main.main"], &["Math.multiply"], false).is_ok());
+        assert!(check_program(&units, &["Main.main"], &[], true).is_ok());
+    }
+
+    #[test]
+    fn test_check_program_errors() {
+        let units = vec![
+            unit("Main", "function Main.main 0\ncall Output.printInt 1\ngoto END\nreturn\n"),
+            unit("Other", "function Main.main 0\nlabel END\nreturn\n"),
+        ];
+        let errors = check_program(&units, &["Sys.init"], &[], false).unwrap_err();
+        assert_eq!(
+            errors,
+            vec![
+                "Main: Main.main jumps to undefined label END".to_string(),
+                "Other: function Main.main is already defined in Main".to_string(),
+                "entry point Sys.init is not defined".to_string(),
+                "Main: Main.main calls undefined function Output.printInt".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_program_static_overflow() {
+        let code: String = (0..200).map(|i| format!("push static {i}\n")).collect();
+        let units = vec![unit("A", &code), unit("B", &code)];
+        let errors = check_program(&units, &[], &[], false).unwrap_err();
+        assert_eq!(
+            errors,
+            vec!["program uses 400 static variables, but only 240 fit between RAM 16 and 255".to_string()]
+        );
+        assert!(check_program(&units[..1], &[], &[], false).is_ok());
+    }
+}
@@ -0,0 +1,27 @@
+use std::process::{Command, Output};
+
+/// Sums 1 to 10 into static 0 and stores `Main.sub(6, 7)` into static 1, so
+/// the program prints `RAM[16] = 55` and `RAM[17] = -1` for `16 2`.
+pub(crate) const SUM_PROGRAM: &str = "function Main.main 1\npush constant 10\npop local 0\nlabel LOOP\npush local 0\npush static 0\nadd\npop static 0\npush local 0\npush constant 1\nsub\npop local 0\npush local 0\nif-goto LOOP\npush constant 6\npush constant 7\ncall Main.sub 2\npop static 1\npush constant 0\nreturn\nfunction Main.sub 0\npush argument 0\npush argument 1\nsub\nreturn\n";
+
+/// Compiles a generated C or assembly file with `cc` in a temporary directory
+/// and runs it with `args`. Returns `None` where there is no C compiler.
+pub(crate) fn compile_and_run(name: &str, file_name: &str, code: &str, args: &[&str]) -> Option<Output> {
+    if Command::new("cc").arg("--version").output().is_err() {
+        return None;
+    }
+
+    let dir = std::env::temp_dir().join(format!("vmtrans-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join(file_name), code).unwrap();
+    let compiled = Command::new("cc")
+        .current_dir(&dir)
+        .args([file_name, "-o", "prog"])
+        .status()
+        .unwrap();
+    let output = Command::new(dir.join("prog")).args(args).output();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(compiled.success());
+    Some(output.unwrap())
+}
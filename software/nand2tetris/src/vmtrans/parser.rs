@@ -1,126 +1,183 @@
-use crate::grammarous::stream::Stream;
+use crate::grammarous::stream::{BufferedStream, Stream};
 use crate::grammarous::string_char_stream::StringCharStream;
 use crate::vmtrans::ast::{ASTNode, Segment};
-use crate::vmtrans::lexer::Lexer;
+use crate::vmtrans::lexer::{Lexer, VmToken};
 use crate::vmtrans::token_type::TokenType::*;
+use std::fmt::{Display, Formatter};
 
-pub fn parse_vm_code(code: &str) -> Result<ASTNode, String> {
+#[derive(Debug, PartialEq, Clone)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+#[cfg(test)]
+pub fn parse_vm_code(code: &str) -> Result<ASTNode, Vec<ParseError>> {
+    parse_vm_code_with_lines(code).map(|(program, _)| program)
+}
+
+/// Like `parse_vm_code`, but also returns the line of every command.
+pub fn parse_vm_code_with_lines(code: &str) -> Result<(ASTNode, Vec<usize>), Vec<ParseError>> {
     let mut stream = StringCharStream::new(code);
     program(&mut stream)
 }
 
-fn program(stream: &mut dyn Stream<char>) -> Result<ASTNode, String> {
+fn program(stream: &mut dyn Stream<char>) -> Result<(ASTNode, Vec<usize>), Vec<ParseError>> {
     let mut lexer = Lexer::new(stream);
+    let mut tokens = Tokens::new(&mut lexer);
     let mut commands = Vec::new();
+    let mut lines = Vec::new();
+    let mut errors = Vec::new();
 
-    loop {
-        if let Some(token) = lexer.advance() {
-            let command = match token.token_type {
-                Push => push(&mut lexer)?,
-                Pop => pop(&mut lexer)?,
-                Add => ASTNode::Add,
-                Sub => ASTNode::Sub,
-                Neg => ASTNode::Neg,
-                Eq => ASTNode::Eq,
-                Gt => ASTNode::Gt,
-                Lt => ASTNode::Lt,
-                And => ASTNode::And,
-                Or => ASTNode::Or,
-                Not => ASTNode::Not,
-                Label => label(&mut lexer)?,
-                Goto => goto_command(&mut lexer)?,
-                IfGoto => if_goto(&mut lexer)?,
-                Function => function(&mut lexer)?,
-                Call => call(&mut lexer)?,
-                Return => ASTNode::Return,
-                _ => {
-                    return Err(format!("Unexpected token: {:?}", token));
-                }
-            };
-            commands.push(command);
-        } else {
-            break;
+    while let Some(token) = tokens.advance() {
+        match command(&mut tokens, &token) {
+            Ok(command) => {
+                commands.push(command);
+                lines.push(token.line);
+            }
+            Err(error) => {
+                errors.push(error);
+                tokens.skip_line(token.line);
+            }
         }
     }
 
-    Ok(ASTNode::Program { commands })
+    if errors.is_empty() {
+        Ok((ASTNode::Program { commands }, lines))
+    } else {
+        Err(errors)
+    }
 }
 
-fn call(lexer: &mut Lexer) -> Result<ASTNode, String> {
-    let name_token = lexer.advance().ok_or("Expected function name after 'call'")?;
-    let n_args_token = lexer.advance().ok_or("Expected number of arguments after function name")?;
+/// Token source for the parser. Every VM command occupies exactly one line,
+/// so the operands of a command are only taken from the line it starts on.
+struct Tokens<'a> {
+    stream: BufferedStream<'a, VmToken>,
+    line: usize,
+    column: usize,
+}
 
-    let name = match name_token.token_type {
-        Name(name) => name,
-        _ => return Err(format!("Invalid function name: {:?}", name_token)),
-    };
+impl<'a> Tokens<'a> {
+    fn new(lexer: &'a mut dyn Stream<VmToken>) -> Self {
+        Self {
+            stream: BufferedStream::new(lexer),
+            line: 1,
+            column: 1,
+        }
+    }
 
-    let n_args = match n_args_token.token_type {
-        Number(n) => n,
-        _ => return Err(format!("Invalid number of arguments: {:?}", n_args_token)),
-    };
+    fn advance(&mut self) -> Option<VmToken> {
+        let token = self.stream.advance()?;
+        self.line = token.line;
+        self.column = token.column + token.lexeme.chars().count();
+        Some(token)
+    }
 
-    Ok(ASTNode::Call { name, n_args })
-}
+    fn operand(&mut self, expected: &str) -> Result<VmToken, ParseError> {
+        match self.stream.peek() {
+            Some(token) if token.line == self.line => Ok(self.advance().unwrap()),
+            _ => Err(self.error_at_end_of_line(expected)),
+        }
+    }
 
-fn function(lexer: &mut Lexer) -> Result<ASTNode, String> {
-    let name_token = lexer.advance().ok_or("Expected function name after 'function'")?;
-    let n_vars_token = lexer.advance().ok_or("Expected number of local variables after function name")?;
+    fn skip_line(&mut self, line: usize) {
+        while let Some(token) = self.stream.peek() {
+            if token.line != line {
+                break;
+            }
+            self.advance();
+        }
+    }
 
-    let name = match name_token.token_type {
-        Name(name) => name,
-        _ => return Err(format!("Invalid function name: {:?}", name_token)),
-    };
+    fn error_at_end_of_line(&self, expected: &str) -> ParseError {
+        ParseError {
+            line: self.line,
+            column: self.column,
+            message: format!("expected {expected}, found end of line"),
+        }
+    }
+}
 
-    let n_vars = match n_vars_token.token_type {
-        Number(n) => n,
-        _ => return Err(format!("Invalid number of local variables: {:?}", n_vars_token)),
+fn unexpected(token: &VmToken, expected: &str) -> ParseError {
+    let found = match &token.token_type {
+        Error(_) => format!("invalid token '{}'", token.lexeme),
+        _ => format!("'{}'", token.lexeme),
     };
-
-    Ok(ASTNode::Function { name, n_locals: n_vars })
+    ParseError {
+        line: token.line,
+        column: token.column,
+        message: format!("expected {expected}, found {found}"),
+    }
 }
 
-fn if_goto(lexer: &mut Lexer) -> Result<ASTNode, String> {
-    let label_token = lexer.advance().ok_or("Expected label name after 'if-goto'")?;
-    match label_token.token_type {
-        Name(label) => Ok(ASTNode::IfGoto { label }),
-        _ => Err(format!("Invalid label name: {:?}", label_token)),
-    }
+fn command(tokens: &mut Tokens, token: &VmToken) -> Result<ASTNode, ParseError> {
+    Ok(match token.token_type {
+        Push => push(tokens)?,
+        Pop => pop(tokens)?,
+        Add => ASTNode::Add,
+        Sub => ASTNode::Sub,
+        Neg => ASTNode::Neg,
+        Eq => ASTNode::Eq,
+        Gt => ASTNode::Gt,
+        Lt => ASTNode::Lt,
+        And => ASTNode::And,
+        Or => ASTNode::Or,
+        Not => ASTNode::Not,
+        Label => ASTNode::Label { name: name(tokens, "label name")? },
+        Goto => ASTNode::Goto { label: name(tokens, "label name")? },
+        IfGoto => ASTNode::IfGoto { label: name(tokens, "label name")? },
+        Function => function(tokens)?,
+        Call => call(tokens)?,
+        Return => ASTNode::Return,
+        _ => return Err(unexpected(token, "command")),
+    })
 }
 
-fn goto_command(lexer: &mut Lexer) -> Result<ASTNode, String> {
-    let label_token = lexer.advance().ok_or("Expected label name after 'goto'")?;
-    match label_token.token_type {
-        Name(label) => Ok(ASTNode::Goto { label }),
-        _ => Err(format!("Invalid label name: {:?}", label_token)),
-    }
+fn call(tokens: &mut Tokens) -> Result<ASTNode, ParseError> {
+    let name = name(tokens, "function name")?;
+    let n_args = number(tokens, "number of arguments")?;
+
+    Ok(ASTNode::Call { name, n_args })
 }
 
-fn label(lexer: &mut Lexer) -> Result<ASTNode, String> {
-    let name_token = lexer.advance().ok_or("Expected label name after 'label'")?;
-    match name_token.token_type {
-        Name(name) => Ok(ASTNode::Label { name }),
-        _ => Err(format!("Invalid label name: {:?}", name_token)),
-    }
+fn function(tokens: &mut Tokens) -> Result<ASTNode, ParseError> {
+    let name = name(tokens, "function name")?;
+    let n_locals = number(tokens, "number of local variables")?;
+
+    Ok(ASTNode::Function { name, n_locals })
 }
 
-fn push(lexer: &mut Lexer) -> Result<ASTNode, String> {
-    let segment = segment(lexer)?;
-    let index = index(lexer)?;
+fn push(tokens: &mut Tokens) -> Result<ASTNode, ParseError> {
+    let (segment, _) = segment(tokens)?;
+    let index = index(tokens, segment)?;
 
     Ok(ASTNode::Push { segment, index })
 }
 
-fn pop(lexer: &mut Lexer) -> Result<ASTNode, String> {
-    let segment = segment(lexer)?;
-    let index = index(lexer)?;
+fn pop(tokens: &mut Tokens) -> Result<ASTNode, ParseError> {
+    let (segment, segment_token) = segment(tokens)?;
+    if segment == Segment::Constant {
+        return Err(ParseError {
+            line: segment_token.line,
+            column: segment_token.column,
+            message: "cannot pop into the constant segment".to_string(),
+        });
+    }
+    let index = index(tokens, segment)?;
 
     Ok(ASTNode::Pop { segment, index })
 }
 
-fn segment(lexer: &mut Lexer) -> Result<Segment, String> {
-    let segment_token = lexer.advance().ok_or("Expected segment after 'push'")?;
-    Ok(match segment_token.token_type {
+fn segment(tokens: &mut Tokens) -> Result<(Segment, VmToken), ParseError> {
+    let segment_token = tokens.operand("segment")?;
+    let segment = match segment_token.token_type {
         Argument => Segment::Argument,
         Local => Segment::Local,
         Static => Segment::Static,
@@ -129,15 +186,40 @@ fn segment(lexer: &mut Lexer) -> Result<Segment, String> {
         That => Segment::That,
         Pointer => Segment::Pointer,
         Temp => Segment::Temp,
-        _ => return Err(format!("Invalid segment: {:?}", segment_token)),
-    })
+        _ => return Err(unexpected(&segment_token, "segment")),
+    };
+    Ok((segment, segment_token))
 }
 
-fn index(lexer: &mut Lexer) -> Result<u16, String> {
-    let index_token = lexer.advance().ok_or("Expected index")?;
-    match index_token.token_type {
-        Number(i) => Ok(i),
-        _ => Err(format!("Invalid index: {:?}", index_token)),
+fn index(tokens: &mut Tokens, segment: Segment) -> Result<u16, ParseError> {
+    let index_token = tokens.operand("index")?;
+    let index = match index_token.token_type {
+        Number(i) => i,
+        _ => return Err(unexpected(&index_token, "index")),
+    };
+    match segment.max_index() {
+        Some(max_index) if index > max_index => Err(ParseError {
+            line: index_token.line,
+            column: index_token.column,
+            message: format!("index {index} is out of range for segment {segment} (0..{max_index})"),
+        }),
+        _ => Ok(index),
+    }
+}
+
+fn name(tokens: &mut Tokens, expected: &str) -> Result<String, ParseError> {
+    let name_token = tokens.operand(expected)?;
+    match name_token.token_type {
+        Name(name) => Ok(name),
+        _ => Err(unexpected(&name_token, expected)),
+    }
+}
+
+fn number(tokens: &mut Tokens, expected: &str) -> Result<u16, ParseError> {
+    let number_token = tokens.operand(expected)?;
+    match number_token.token_type {
+        Number(n) => Ok(n),
+        _ => Err(unexpected(&number_token, expected)),
     }
 }
 
@@ -236,4 +318,36 @@ mod tests {
             _ => panic!("Expected Program node"),
         }
     }
+
+    #[test]
+    fn test_parse_index_ranges() {
+        let input = "pop constant 1\npush temp 8\npop pointer 2\npush constant 32768\npush constant 32767\n";
+        let errors = parse_vm_code(input).unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "1:5: cannot pop into the constant segment",
+                "2:11: index 8 is out of range for segment temp (0..7)",
+                "3:13: index 2 is out of range for segment pointer (0..1)",
+                "4:15: index 32768 is out of range for segment constant (0..32767)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let input = "push constant\npop foo 1\nadd 3\npush local 2\ncall Foo.bar\n";
+        let errors = parse_vm_code(input).unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "1:14: expected index, found end of line",
+                "2:5: expected segment, found 'foo'",
+                "3:5: expected command, found '3'",
+                "5:13: expected number of arguments, found end of line",
+            ]
+        );
+    }
 }
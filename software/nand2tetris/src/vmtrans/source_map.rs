@@ -0,0 +1,95 @@
+use crate::vmtrans::json::json_string;
+use std::rc::Rc;
+
+/// The VM command a piece of generated code was translated from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: usize,
+    /// Function the command belongs to, `None` before the first `function`.
+    pub function: Option<String>,
+    pub command: String,
+}
+
+/// Consecutive Hack instructions translated from the same VM command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceMapEntry {
+    /// ROM address of the first instruction.
+    pub start: usize,
+    /// ROM address after the last instruction.
+    pub end: usize,
+    pub location: SourceLocation,
+}
+
+/// Maps the ROM addresses of a translated program to VM commands.
+///
+/// Instructions without a VM command, like the bootstrap code, have no entry.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    pub entries: Vec<SourceMapEntry>,
+}
+
+impl SourceMap {
+    /// Builds the map from assembly lines and the location each was emitted
+    /// for. Label declarations occupy no ROM address.
+    pub(crate) fn new(asm_lines: &[String], locations: &[Option<Rc<SourceLocation>>]) -> Self {
+        let mut entries: Vec<SourceMapEntry> = vec![];
+        let mut last_location: Option<&Rc<SourceLocation>> = None;
+        let mut address = 0;
+
+        for (line, location) in asm_lines.iter().zip(locations) {
+            if line.starts_with('(') {
+                continue;
+            }
+            if let Some(location) = location {
+                match (entries.last_mut(), last_location) {
+                    (Some(entry), Some(last)) if Rc::ptr_eq(last, location) && entry.end == address => {
+                        entry.end = address + 1;
+                    }
+                    _ => entries.push(SourceMapEntry {
+                        start: address,
+                        end: address + 1,
+                        location: location.as_ref().clone(),
+                    }),
+                }
+                last_location = Some(location);
+            }
+            address += 1;
+        }
+
+        Self { entries }
+    }
+
+    /// Location of the instruction at `address`.
+    pub fn find(&self, address: usize) -> Option<&SourceLocation> {
+        let index = self.entries.partition_point(|entry| entry.end <= address);
+        self.entries
+            .get(index)
+            .filter(|entry| entry.start <= address)
+            .map(|entry| &entry.location)
+    }
+
+    pub fn to_json(&self) -> String {
+        let entries: Vec<String> = self
+            .entries
+            .iter()
+            .map(|entry| {
+                let location = &entry.location;
+                let function = match &location.function {
+                    Some(function) => json_string(function),
+                    None => "null".to_string(),
+                };
+                format!(
+                    "    {{\"start\": {}, \"end\": {}, \"file\": {}, \"line\": {}, \"function\": {}, \"command\": {}}}",
+                    entry.start,
+                    entry.end,
+                    json_string(&location.file),
+                    location.line,
+                    function,
+                    json_string(&location.command)
+                )
+            })
+            .collect();
+        format!("{{\n  \"commands\": [\n{}\n  ]\n}}\n", entries.join(",\n"))
+    }
+}
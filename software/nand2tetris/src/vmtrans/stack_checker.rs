@@ -0,0 +1,173 @@
+use crate::vmtrans::ast::{ASTNode, Segment};
+use std::collections::HashMap;
+
+/// Verifies the stack discipline of every VM function.
+///
+/// The stack depth relative to the frame of the function is tracked along all
+/// paths through the body. Reported are pops from an empty stack, `return`
+/// with an empty stack, labels that are reached with different depths and
+/// accesses to locals beyond the number declared by `function`. `lines` holds
+/// the source line of every command of every unit, as returned by
+/// `parse_vm_code_with_lines`.
+pub fn check_stack_depths(units: &[(String, ASTNode)], lines: &[Vec<usize>]) -> Result<(), Vec<String>> {
+    let mut errors = vec![];
+
+    for ((unit_name, program), unit_lines) in units.iter().zip(lines) {
+        let ASTNode::Program { commands } = program else {
+            continue;
+        };
+        let mut starts: Vec<usize> = commands
+            .iter()
+            .enumerate()
+            .filter(|(_, command)| matches!(command, ASTNode::Function { .. }))
+            .map(|(i, _)| i)
+            .collect();
+        starts.push(commands.len());
+        for range in starts.windows(2) {
+            let function = &commands[range[0]..range[1]];
+            let ASTNode::Function { name, .. } = &function[0] else {
+                continue;
+            };
+            let mut function_errors = vec![];
+            stack_depths(function, &mut function_errors);
+            for (index, message) in function_errors {
+                let command = &function[index];
+                errors.push(format!(
+                    "{unit_name}:{}: {name}: {}: {message}",
+                    unit_lines[range[0] + index],
+                    command.to_command_string()
+                ));
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Checks one function; `commands[0]` is its `function` command. Errors are
+/// reported with the index of the command in `commands`.
+///
+/// Returns the stack depth before each command of the body, `None` for
+/// commands that cannot be reached.
+pub(crate) fn stack_depths(commands: &[ASTNode], errors: &mut Vec<(usize, String)>) -> Vec<Option<i32>> {
+    let ASTNode::Function { n_locals, .. } = &commands[0] else {
+        return vec![];
+    };
+    let body = &commands[1..];
+    let mut report = |index: usize, message: String| errors.push((index + 1, message));
+
+    let labels: HashMap<&str, usize> = body
+        .iter()
+        .enumerate()
+        .filter_map(|(i, command)| match command {
+            ASTNode::Label { name } => Some((name.as_str(), i)),
+            _ => None,
+        })
+        .collect();
+
+    let mut depths: Vec<Option<i32>> = vec![None; body.len()];
+    let mut worklist = vec![(0, 0)];
+
+    while let Some((index, depth)) = worklist.pop() {
+        if index >= body.len() {
+            continue;
+        }
+        match depths[index] {
+            Some(known) if known != depth => {
+                report(index, format!("reached with different stack depths ({known} and {depth})"));
+                continue;
+            }
+            Some(_) => continue,
+            None => depths[index] = Some(depth),
+        }
+
+        let command = &body[index];
+        let (needed, effect) = stack_effect(command);
+        if depth < needed {
+            let message = match command {
+                ASTNode::Return => "return with an empty stack".to_string(),
+                _ => format!(
+                    "needs {needed} value{} on the stack, but it holds {depth}",
+                    if needed == 1 { "" } else { "s" }
+                ),
+            };
+            report(index, message);
+        }
+        if let ASTNode::Push { segment: Segment::Local, index: local }
+        | ASTNode::Pop { segment: Segment::Local, index: local } = command
+            && local >= n_locals
+        {
+            report(index, format!("local {local} is beyond the {n_locals} locals of the function"));
+        }
+
+        // Continue as if the missing values were there to avoid follow-up errors
+        let depth = depth.max(needed) + effect;
+        match command {
+            ASTNode::Return => {}
+            ASTNode::Goto { label } => {
+                if let Some(&target) = labels.get(label.as_str()) {
+                    worklist.push((target, depth));
+                }
+            }
+            ASTNode::IfGoto { label } => {
+                if let Some(&target) = labels.get(label.as_str()) {
+                    worklist.push((target, depth));
+                }
+                worklist.push((index + 1, depth));
+            }
+            _ => worklist.push((index + 1, depth)),
+        }
+    }
+
+    depths
+}
+
+/// Number of values a command needs on the stack and its net effect on the depth.
+fn stack_effect(command: &ASTNode) -> (i32, i32) {
+    match command {
+        ASTNode::Push { .. } => (0, 1),
+        ASTNode::Pop { .. } | ASTNode::IfGoto { .. } => (1, -1),
+        ASTNode::Add | ASTNode::Sub | ASTNode::And | ASTNode::Or
+        | ASTNode::Eq | ASTNode::Gt | ASTNode::Lt => (2, -1),
+        ASTNode::Neg | ASTNode::Not => (1, 0),
+        ASTNode::Call { n_args, .. } => (*n_args as i32, 1 - *n_args as i32),
+        ASTNode::Return => (1, 0),
+        _ => (0, 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vmtrans::parser::parse_vm_code_with_lines;
+
+    fn check(code: &str) -> Result<(), Vec<String>> {
+        let (program, lines) = parse_vm_code_with_lines(code).unwrap();
+        check_stack_depths(&[("Main".to_string(), program)], &[lines])
+    }
+
+    #[test]
+    fn test_balanced_function() {
+        let code = "function Main.loop 1\nlabel LOOP\npush local 0\nif-goto END\npush constant 1\npop local 0\ngoto LOOP\nlabel END\npush constant 0\nreturn\n";
+        assert!(check(code).is_ok());
+    }
+
+    #[test]
+    fn test_stack_errors() {
+        let code = "function Main.bad 0\npush constant 1\nif-goto SKIP\npush constant 2\nlabel SKIP\nadd\nreturn\n\n// no locals left\nfunction Main.empty 1\npop local 1\nreturn\n";
+        assert_eq!(
+            check(code).unwrap_err(),
+            vec![
+                "Main:6: Main.bad: add: needs 2 values on the stack, but it holds 1",
+                "Main:5: Main.bad: label SKIP: reached with different stack depths (1 and 0)",
+                "Main:11: Main.empty: pop local 1: needs 1 value on the stack, but it holds 0",
+                "Main:11: Main.empty: pop local 1: local 1 is beyond the 1 locals of the function",
+                "Main:12: Main.empty: return: return with an empty stack",
+            ]
+        );
+    }
+}
@@ -0,0 +1,535 @@
+use crate::vmtrans::ast::ASTNode;
+use crate::vmtrans::c_writer::write_c_program;
+use crate::vmtrans::code_writer::CodeWriter;
+use crate::vmtrans::inliner::inline_functions;
+use crate::vmtrans::linker::check_program;
+use crate::vmtrans::parser::parse_vm_code_with_lines;
+use crate::vmtrans::source_map::SourceMap;
+use crate::vmtrans::stack_checker::check_stack_depths;
+use crate::vmtrans::x86_writer::{write_x86_program, NATIVE_FUNCTIONS};
+use std::path::Path;
+
+/// A VM source held in memory. The file stem of `name` is used as prefix for
+/// the static variables of the source, so `Main.vm` and `dir/Main.vm` both
+/// yield `Main.<index>`. The full name appears in error messages.
+#[derive(Debug, Clone)]
+pub struct VmSource {
+    pub name: String,
+    pub code: String,
+}
+
+impl VmSource {
+    pub fn new(name: &str, code: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            code: code.to_string(),
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    /// Hack assembly
+    Hack,
+    /// Portable C that simulates the Hack RAM
+    C,
+    /// GNU as x86-64 assembly for Linux with a native Math/Memory runtime
+    X86_64,
+}
+
+impl Target {
+    pub fn file_extension(&self) -> &'static str {
+        match self {
+            Target::Hack => "asm",
+            Target::C => "c",
+            Target::X86_64 => "s",
+        }
+    }
+
+    /// Functions the target implements itself, so they need no VM definition.
+    fn external_functions(&self) -> Vec<&'static str> {
+        match self {
+            Target::Hack => vec![],
+            Target::C => vec!["Sys.halt"],
+            Target::X86_64 => NATIVE_FUNCTIONS
+                .iter()
+                .map(|(name, _)| *name)
+                .chain(["Sys.halt"])
+                .collect(),
+        }
+    }
+}
+
+/// Initial values of the pointers, set before anything else runs. Pointers
+/// without a value are left as they are.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryLayout {
+    /// Initial value of SP.
+    pub stack_base: u16,
+    pub local: Option<u16>,
+    pub argument: Option<u16>,
+    pub this: Option<u16>,
+    pub that: Option<u16>,
+}
+
+impl Default for MemoryLayout {
+    fn default() -> Self {
+        Self {
+            stack_base: 256,
+            local: None,
+            argument: None,
+            this: None,
+            that: None,
+        }
+    }
+}
+
+/// What the program does once the entry point returns or, without bootstrap
+/// code, once the last command has been executed.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HaltMode {
+    /// Jump to itself forever
+    Loop,
+    /// Call `Sys.halt`
+    SysHalt,
+}
+
+#[derive(Debug, Clone)]
+pub struct TranslateOptions {
+    pub target: Target,
+    /// Emit the bootstrap code that calls `entry_point`. Without it, execution
+    /// starts at the first command of the first source.
+    pub bootstrap: bool,
+    pub entry_point: String,
+    /// Pointers set at the start of the program, defaults to SP = 256 with
+    /// bootstrap code and to nothing without. The call of the entry point sets
+    /// up its own LCL and ARG (Hack target only).
+    pub memory_layout: Option<MemoryLayout>,
+    /// How the program ends (Hack target only; the other targets exit).
+    pub halt: HaltMode,
+    /// Accept calls to functions that no source defines, such as the OS when
+    /// translating a single file or a library (Hack target only).
+    pub allow_external: bool,
+    /// Verify the stack discipline of every function before translating.
+    pub verify_stack: bool,
+    /// 0 translates every command as is; 1 and above turn tail calls into
+    /// jumps that reuse the frame of the caller; 2 and above also keep the top
+    /// of the stack in D between commands (Hack target only).
+    pub optimization_level: u8,
+    /// Inline leaf functions with at most this many commands at their call
+    /// sites; 0 disables inlining.
+    pub inline_limit: usize,
+}
+
+impl Default for TranslateOptions {
+    fn default() -> Self {
+        Self {
+            target: Target::Hack,
+            bootstrap: true,
+            entry_point: "Sys.init".to_string(),
+            memory_layout: None,
+            halt: HaltMode::Loop,
+            allow_external: false,
+            verify_stack: false,
+            optimization_level: 0,
+            inline_limit: 0,
+        }
+    }
+}
+
+/// Receives progress notifications while sources are translated.
+pub trait TranslateProgress {
+    fn source_started(&mut self, _name: &str) {}
+    fn source_finished(&mut self, _name: &str, _success: bool) {}
+}
+
+/// Translates the given sources into one program for the target platform.
+///
+/// All sources are parsed and checked as a whole before any code is generated.
+/// Nothing is printed; progress is reported to `progress` if given.
+pub fn translate(
+    sources: &[VmSource],
+    options: &TranslateOptions,
+    progress: Option<&mut dyn TranslateProgress>,
+) -> Result<String, String> {
+    translate_with_source_map(sources, options, progress).map(|(code, _)| code)
+}
+
+/// Like `translate`, but also returns the map from the generated instructions
+/// to the VM commands they stem from (Hack target only).
+pub fn translate_with_source_map(
+    sources: &[VmSource],
+    options: &TranslateOptions,
+    mut progress: Option<&mut dyn TranslateProgress>,
+) -> Result<(String, Option<SourceMap>), String> {
+    if sources.is_empty() {
+        return Err("No source given".to_string());
+    }
+
+    let mut units = vec![];
+    let mut lines = vec![];
+    for source in sources {
+        if let Some(progress) = progress.as_mut() {
+            progress.source_started(&source.name);
+        }
+        let unit = parse_source(source);
+        if let Some(progress) = progress.as_mut() {
+            progress.source_finished(&source.name, unit.is_ok());
+        }
+        let (static_prefix, program, unit_lines) = unit?;
+        units.push((static_prefix, program));
+        lines.push(unit_lines);
+    }
+
+    let mut entry_points: Vec<&str> = vec![];
+    if options.bootstrap {
+        entry_points.push(options.entry_point.as_str());
+    }
+    if options.target == Target::Hack && options.halt == HaltMode::SysHalt {
+        entry_points.push("Sys.halt");
+    }
+    let external_functions = options.target.external_functions();
+    check_program(&units, &entry_points, &external_functions, options.allow_external).map_err(|errors| {
+        format!("Link errors:\n{}", errors.join("\n"))
+    })?;
+
+    if options.verify_stack {
+        check_stack_depths(&units, &lines).map_err(|errors| {
+            format!("Stack errors:\n{}", errors.join("\n"))
+        })?;
+    }
+
+    if options.inline_limit > 0 {
+        let (inlined_units, origins) = inline_functions(&units, options.inline_limit, &external_functions);
+        units = inlined_units;
+        lines = origins
+            .iter()
+            .zip(&lines)
+            .map(|(unit_origins, unit_lines)| unit_origins.iter().map(|&i| unit_lines[i]).collect())
+            .collect();
+    }
+
+    let bootstrap = options.bootstrap.then_some(options.entry_point.as_str());
+    match options.target {
+        Target::Hack => {
+            let (code, source_map) = write_hack_program(&units, sources, lines, options)?;
+            Ok((code, Some(source_map)))
+        }
+        Target::C => Ok((write_c_program(&units, bootstrap)?, None)),
+        Target::X86_64 => Ok((write_x86_program(&units, bootstrap)?, None)),
+    }
+}
+
+fn write_hack_program(
+    units: &[(String, ASTNode)],
+    sources: &[VmSource],
+    lines: Vec<Vec<usize>>,
+    options: &TranslateOptions,
+) -> Result<(String, SourceMap), String> {
+    let layout = match &options.memory_layout {
+        Some(layout) => Some(layout.clone()),
+        None => options.bootstrap.then(MemoryLayout::default),
+    };
+    let mut asm_lines = layout.map(|layout| translate_memory_layout(&layout)).unwrap_or_default();
+    if options.bootstrap {
+        asm_lines.extend(translate_start_of_program(&options.entry_point, options.halt));
+    }
+    let mut locations = vec![None; asm_lines.len()];
+
+    for (((static_prefix, program), source), unit_lines) in units.iter().zip(sources).zip(lines) {
+        let mut code_writer = CodeWriter::new(static_prefix.to_string());
+        code_writer.set_tail_calls(options.optimization_level >= 1);
+        code_writer.set_cache_top(options.optimization_level >= 2);
+        code_writer.set_source(source.name.clone(), unit_lines);
+        asm_lines.extend(code_writer.write_program(program)?);
+        locations.extend(code_writer.get_line_locations());
+    }
+
+    if !options.bootstrap {
+        asm_lines.extend(translate_end_of_program(&units[0].0, options.halt));
+    }
+    locations.resize(asm_lines.len(), None);
+
+    let source_map = SourceMap::new(&asm_lines, &locations);
+    Ok((asm_lines.join("\n") + "\n", source_map))
+}
+
+/// Parses a source into its static prefix, its program and the line of every
+/// command of the program.
+pub(crate) fn parse_source(source: &VmSource) -> Result<(String, ASTNode, Vec<usize>), String> {
+    let static_prefix = static_prefix(&source.name)?;
+    let (program, lines) = parse_vm_code_with_lines(&source.code).map_err(|errors| {
+        let messages: Vec<String> = errors
+            .iter()
+            .map(|error| format!("{}:{error}", source.name))
+            .collect();
+        messages.join("\n")
+    })?;
+    Ok((static_prefix, program, lines))
+}
+
+fn static_prefix(name: &str) -> Result<String, String> {
+    Path::new(name)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .map(|s| s.to_string())
+        .ok_or_else(|| format!("Invalid source name: {}", name))
+}
+
+fn translate_memory_layout(layout: &MemoryLayout) -> Vec<String> {
+    let mut lines = vec![
+        format!("@{} // <--- Start", layout.stack_base),
+        "D=A".to_string(),
+        "@SP".to_string(),
+        "M=D".to_string(),
+    ];
+    let pointers = [("LCL", layout.local), ("ARG", layout.argument), ("THIS", layout.this), ("THAT", layout.that)];
+    for (pointer, value) in pointers {
+        if let Some(value) = value {
+            lines.extend([format!("@{value}"), "D=A".to_string(), format!("@{pointer}"), "M=D".to_string()]);
+        }
+    }
+    lines
+}
+
+fn translate_start_of_program(entry_point: &str, halt: HaltMode) -> Vec<String> {
+    // Call the entry point
+    let mut lines = vec![];
+    let mut code_writer = CodeWriter::new("Sys".to_string());
+    code_writer.write_call(entry_point, 0);
+    lines.extend(code_writer.get_lines());
+    lines.extend(translate_end_of_program("Sys", halt));
+    lines
+}
+
+fn translate_end_of_program(prefix: &str, halt: HaltMode) -> Vec<String> {
+    let end_label = format!("{prefix}.end");
+    match halt {
+        HaltMode::Loop => vec![
+            format!("({end_label}) // <-- end of program"),
+            format!("@{end_label}"),
+            "0;JMP".to_string(),
+        ],
+        HaltMode::SysHalt => {
+            let mut code_writer = CodeWriter::new(end_label);
+            code_writer.write_call("Sys.halt", 0);
+            let mut lines = code_writer.get_lines();
+            lines[0] += " // <-- end of program";
+            lines
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct RecordingProgress {
+        events: Vec<String>,
+    }
+
+    impl TranslateProgress for RecordingProgress {
+        fn source_started(&mut self, name: &str) {
+            self.events.push(format!("start {name}"));
+        }
+        fn source_finished(&mut self, name: &str, success: bool) {
+            self.events.push(format!("finish {name} {success}"));
+        }
+    }
+
+    #[test]
+    fn test_translate_without_bootstrap() {
+        let sources = vec![VmSource::new("dir/Simple.vm", "push constant 7\npop static 1\n")];
+        let options = TranslateOptions {
+            bootstrap: false,
+            ..TranslateOptions::default()
+        };
+        let mut progress = RecordingProgress { events: vec![] };
+
+        let asm = translate(&sources, &options, Some(&mut progress)).unwrap();
+
+        assert!(asm.starts_with("@7 // <- push constant 7\n"));
+        assert!(asm.contains("@Simple.1\n"));
+        assert!(asm.ends_with("(Simple.end) // <-- end of program\n@Simple.end\n0;JMP\n"));
+        assert_eq!(progress.events, vec!["start dir/Simple.vm", "finish dir/Simple.vm true"]);
+    }
+
+    #[test]
+    fn test_translate_with_entry_point() {
+        let sources = vec![VmSource::new("Main.vm", "function Main.main 0\npush constant 0\nreturn\n")];
+        let options = TranslateOptions {
+            entry_point: "Main.main".to_string(),
+            ..TranslateOptions::default()
+        };
+
+        let asm = translate(&sources, &options, None).unwrap();
+        assert!(asm.contains("@Main.main\n0;JMP\n"));
+
+        let error = translate(&sources, &TranslateOptions::default(), None).unwrap_err();
+        assert_eq!(error, "Link errors:\nentry point Sys.init is not defined");
+    }
+
+    #[test]
+    fn test_translate_with_external_calls() {
+        let sources = vec![VmSource::new("Main.vm", "function Main.main 0\npush constant 1\ncall Output.printInt 1\nreturn\n")];
+        let options = TranslateOptions {
+            bootstrap: false,
+            ..TranslateOptions::default()
+        };
+
+        let error = translate(&sources, &options, None).unwrap_err();
+        assert_eq!(error, "Link errors:\nMain: Main.main calls undefined function Output.printInt");
+
+        let options = TranslateOptions {
+            allow_external: true,
+            ..options
+        };
+        let asm = translate(&sources, &options, None).unwrap();
+        assert!(asm.contains("@Output.printInt\n0;JMP\n"));
+    }
+
+    #[test]
+    fn test_translate_tail_calls() {
+        let sources = vec![VmSource::new(
+            "Main.vm",
+            "function Main.loop 0\npush argument 0\ncall Main.loop 1\nreturn\n",
+        )];
+        let options = TranslateOptions {
+            entry_point: "Main.loop".to_string(),
+            optimization_level: 1,
+            ..TranslateOptions::default()
+        };
+
+        let asm = translate(&sources, &options, None).unwrap();
+        assert!(asm.contains("// <- call Main.loop 1 (tail call)\n"));
+        assert!(!asm.contains("// <- return"));
+        assert!(!asm.contains("(Main.loop$ret.0)"));
+    }
+
+    #[test]
+    fn test_translate_cached_top_of_stack() {
+        let sources = vec![VmSource::new(
+            "Main.vm",
+            "function Main.main 0\npush constant 2\npush constant 3\nadd\npop static 0\nlabel END\ngoto END\n",
+        )];
+        let options = TranslateOptions {
+            entry_point: "Main.main".to_string(),
+            optimization_level: 2,
+            ..TranslateOptions::default()
+        };
+
+        let asm = translate(&sources, &options, None).unwrap();
+        assert!(asm.contains(concat!(
+            "@2 // <- push constant 2\nD=A\n",
+            "@SP // <- push constant 3\nM=M+1\nA=M-1\nM=D\n@3\nD=A\n",
+            "@SP // <- add\nAM=M-1\nD=D+M\n",
+            "@Main.0 // <- pop static 0\nM=D\n",
+        )));
+    }
+
+    #[test]
+    fn test_translate_memory_layout() {
+        let sources = vec![VmSource::new("Main.vm", "function Main.main 0\npush constant 0\nreturn\n")];
+        let options = TranslateOptions {
+            entry_point: "Main.main".to_string(),
+            memory_layout: Some(MemoryLayout {
+                stack_base: 300,
+                local: Some(400),
+                that: Some(3010),
+                ..MemoryLayout::default()
+            }),
+            ..TranslateOptions::default()
+        };
+
+        let asm = translate(&sources, &options, None).unwrap();
+        assert!(asm.starts_with("@300 // <--- Start\nD=A\n@SP\nM=D\n@400\nD=A\n@LCL\nM=D\n@3010\nD=A\n@THAT\nM=D\n@Sys$ret.0\n"));
+        assert!(asm.contains("(Sys$ret.0)\n(Sys.end) // <-- end of program\n@Sys.end\n0;JMP\n"));
+
+        let options = TranslateOptions {
+            halt: HaltMode::SysHalt,
+            ..options
+        };
+        let error = translate(&sources, &options, None).unwrap_err();
+        assert_eq!(error, "Link errors:\nentry point Sys.halt is not defined");
+
+        let options = TranslateOptions {
+            bootstrap: false,
+            halt: HaltMode::Loop,
+            ..options
+        };
+        let asm = translate(&sources, &options, None).unwrap();
+        assert!(asm.starts_with("@300 // <--- Start\n"));
+        assert!(asm.contains("@THAT\nM=D\n(Main.main) // <- function Main.main 0\n"));
+    }
+
+    #[test]
+    fn test_translate_source_map() {
+        let sources = vec![
+            VmSource::new("Sys.vm", "function Sys.init 0\ncall Main.main 0\nreturn\n"),
+            VmSource::new("dir/Main.vm", "// Main\nfunction Main.main 0\n\npush constant 7\nreturn\n"),
+        ];
+        let options = TranslateOptions {
+            optimization_level: 1,
+            ..TranslateOptions::default()
+        };
+
+        let (asm, source_map) = translate_with_source_map(&sources, &options, None).unwrap();
+        let source_map = source_map.unwrap();
+        let instructions: Vec<&str> = asm.lines().filter(|line| !line.starts_with('(')).collect();
+
+        let summary: Vec<(&str, usize, Option<&str>, &str)> = source_map
+            .entries
+            .iter()
+            .map(|entry| {
+                let location = &entry.location;
+                (location.file.as_str(), location.line, location.function.as_deref(), location.command.as_str())
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("Sys.vm", 2, Some("Sys.init"), "call Main.main 0"),
+                ("dir/Main.vm", 4, Some("Main.main"), "push constant 7"),
+                ("dir/Main.vm", 5, Some("Main.main"), "return"),
+            ]
+        );
+        let push = &source_map.entries[1];
+        assert_eq!(instructions[push.start], "@7 // <- push constant 7");
+        assert_eq!(push.end, source_map.entries[2].start);
+        assert_eq!(source_map.find(push.start + 1).unwrap().command, "push constant 7");
+        assert_eq!(source_map.find(0), None);
+        assert!(source_map.to_json().contains(
+            "{\"start\": 167, \"end\": 174, \"file\": \"dir/Main.vm\", \"line\": 4, \"function\": \"Main.main\", \"command\": \"push constant 7\"}"
+        ));
+    }
+
+    /// Number of Hack instructions, without label declarations.
+    fn instruction_count(sources: &[VmSource], options: &TranslateOptions) -> usize {
+        let asm = translate(sources, options, None).unwrap();
+        asm.lines().filter(|line| !line.starts_with('(')).count()
+    }
+
+    #[test]
+    fn test_cached_top_of_stack_saves_instructions() {
+        let basic_loop = vec![VmSource::new(
+            "BasicLoop.vm",
+            include_str!("../../tests/input/BasicLoop/BasicLoop.vm"),
+        )];
+        let options = TranslateOptions {
+            bootstrap: false,
+            ..TranslateOptions::default()
+        };
+        let level_0 = instruction_count(&basic_loop, &options);
+        let level_2 = instruction_count(&basic_loop, &TranslateOptions { optimization_level: 2, ..options });
+        assert!(level_2 * 3 <= level_0 * 2, "{level_0} instructions at level 0, {level_2} at level 2");
+
+        let fibonacci = vec![
+            VmSource::new("Sys.vm", include_str!("../../tests/input/FibonacciElement/Sys.vm")),
+            VmSource::new("Main.vm", include_str!("../../tests/input/FibonacciElement/Main.vm")),
+        ];
+        let options = TranslateOptions::default();
+        let level_0 = instruction_count(&fibonacci, &options);
+        let level_2 = instruction_count(&fibonacci, &TranslateOptions { optimization_level: 2, ..options });
+        assert!(level_2 < level_0, "{level_0} instructions at level 0, {level_2} at level 2");
+    }
+}
@@ -0,0 +1,615 @@
+use crate::vmtrans::ast::{ASTNode, Segment};
+use crate::vmtrans::linker::allocate_statics;
+use std::collections::HashMap;
+
+/// OS functions that the runtime implements natively, with their entry labels.
+/// Calls to them never reach a VM implementation, so the OS `.vm` files are
+/// only needed for the remaining functions.
+pub(crate) const NATIVE_FUNCTIONS: [(&str, &str); 10] = [
+    ("Math.multiply", "native_math_multiply"),
+    ("Math.divide", "native_math_divide"),
+    ("Math.abs", "native_math_abs"),
+    ("Math.min", "native_math_min"),
+    ("Math.max", "native_math_max"),
+    ("Math.sqrt", "native_math_sqrt"),
+    ("Memory.peek", "native_memory_peek"),
+    ("Memory.poke", "native_memory_poke"),
+    ("Memory.alloc", "native_memory_alloc"),
+    ("Memory.deAlloc", "native_memory_dealloc"),
+];
+
+const PRELUDE: &str = r#"    .bss
+    .align 16
+ram:
+    .skip 131072
+vm_saved_rsp:
+    .skip 8
+heap_free_list:
+    .skip 2
+heap_ready:
+    .skip 1
+
+    .section .rodata
+ram_format:
+    .string "RAM[%d] = %d\n"
+divide_by_zero_message:
+    .ascii "Math.divide: division by zero\n"
+divide_by_zero_message_end:
+heap_overflow_message:
+    .ascii "Memory.alloc: heap overflow\n"
+heap_overflow_message_end:
+
+    .text
+    .globl main
+main:
+    pushq %rbp
+    pushq %r12
+    pushq %r13
+    pushq %r14
+    pushq %r15
+    movl %edi, %r12d
+    movq %rsi, %r13
+    call vm_run
+    cmpl $1, %r12d
+    jle .Lmain_done
+    movq 8(%r13), %rdi
+    call atoi@PLT
+    movl %eax, %r14d
+    movl $1, %r15d
+    cmpl $2, %r12d
+    jle .Lmain_count
+    movq 16(%r13), %rdi
+    call atoi@PLT
+    movl %eax, %r15d
+.Lmain_count:
+    addl %r14d, %r15d
+.Lmain_loop:
+    cmpl %r15d, %r14d
+    jge .Lmain_done
+    leaq ram(%rip), %rax
+    movzwl %r14w, %ecx
+    movswl (%rax,%rcx,2), %edx
+    movl %r14d, %esi
+    leaq ram_format(%rip), %rdi
+    xorl %eax, %eax
+    call printf@PLT
+    incl %r14d
+    jmp .Lmain_loop
+.Lmain_done:
+    xorl %eax, %eax
+    popq %r15
+    popq %r14
+    popq %r13
+    popq %r12
+    popq %rbp
+    ret
+
+native_math_multiply:
+    decw (%rbx)
+    movzwl (%rbx), %ecx
+    movw (%rbx,%rcx,2), %dx
+    movw -2(%rbx,%rcx,2), %ax
+    imulw %dx, %ax
+    movw %ax, -2(%rbx,%rcx,2)
+    ret
+
+native_math_divide:
+    decw (%rbx)
+    movzwl (%rbx), %ecx
+    movw (%rbx,%rcx,2), %si
+    movw -2(%rbx,%rcx,2), %ax
+    testw %si, %si
+    jz vm_divide_by_zero
+    cmpw $-1, %si
+    je .Ldivide_negate
+    cwtd
+    idivw %si
+    movw %ax, -2(%rbx,%rcx,2)
+    ret
+.Ldivide_negate:
+    negw -2(%rbx,%rcx,2)
+    ret
+
+native_math_abs:
+    movzwl (%rbx), %ecx
+    movw -2(%rbx,%rcx,2), %ax
+    movw %ax, %dx
+    negw %dx
+    testw %ax, %ax
+    cmovsw %dx, %ax
+    movw %ax, -2(%rbx,%rcx,2)
+    ret
+
+native_math_min:
+    decw (%rbx)
+    movzwl (%rbx), %ecx
+    movw (%rbx,%rcx,2), %dx
+    movw -2(%rbx,%rcx,2), %ax
+    cmpw %dx, %ax
+    cmovgw %dx, %ax
+    movw %ax, -2(%rbx,%rcx,2)
+    ret
+
+native_math_max:
+    decw (%rbx)
+    movzwl (%rbx), %ecx
+    movw (%rbx,%rcx,2), %dx
+    movw -2(%rbx,%rcx,2), %ax
+    cmpw %dx, %ax
+    cmovlw %dx, %ax
+    movw %ax, -2(%rbx,%rcx,2)
+    ret
+
+native_math_sqrt:
+    movzwl (%rbx), %ecx
+    movswl -2(%rbx,%rcx,2), %eax
+    cvtsi2sd %eax, %xmm0
+    sqrtsd %xmm0, %xmm0
+    cvttsd2si %xmm0, %eax
+    movw %ax, -2(%rbx,%rcx,2)
+    ret
+
+native_memory_peek:
+    movzwl (%rbx), %ecx
+    movzwl -2(%rbx,%rcx,2), %eax
+    movw (%rbx,%rax,2), %ax
+    movw %ax, -2(%rbx,%rcx,2)
+    ret
+
+native_memory_poke:
+    decw (%rbx)
+    movzwl (%rbx), %ecx
+    movw (%rbx,%rcx,2), %dx
+    movzwl -2(%rbx,%rcx,2), %eax
+    movw %dx, (%rbx,%rax,2)
+    movw $0, -2(%rbx,%rcx,2)
+    ret
+
+# First fit over a list of free segments in the heap at RAM 2048 to 16383.
+# A free segment holds its length and the address of the next one, a block
+# keeps its length in the word before the object.
+native_memory_alloc:
+    cmpb $0, heap_ready(%rip)
+    jne .Lalloc_ready
+    movb $1, heap_ready(%rip)
+    movw $2048, heap_free_list(%rip)
+    movw $14336, 4096(%rbx)
+    movw $0, 4098(%rbx)
+.Lalloc_ready:
+    movzwl (%rbx), %ecx
+    movswl -2(%rbx,%rcx,2), %edx
+    cmpl $1, %edx
+    jge .Lalloc_size
+    movl $1, %edx
+.Lalloc_size:
+    incl %edx
+    leaq heap_free_list(%rip), %rdi
+.Lalloc_next:
+    movzwl (%rdi), %eax
+    testl %eax, %eax
+    jz vm_heap_overflow
+    movzwl (%rbx,%rax,2), %esi
+    cmpl %edx, %esi
+    jge .Lalloc_found
+    leaq 2(%rbx,%rax,2), %rdi
+    jmp .Lalloc_next
+.Lalloc_found:
+    movl %esi, %r8d
+    subl %edx, %r8d
+    cmpl $2, %r8d
+    jl .Lalloc_whole
+    movw %r8w, (%rbx,%rax,2)
+    addl %r8d, %eax
+    jmp .Lalloc_done
+.Lalloc_whole:
+    movw 2(%rbx,%rax,2), %r8w
+    movw %r8w, (%rdi)
+    movl %esi, %edx
+.Lalloc_done:
+    movw %dx, (%rbx,%rax,2)
+    incl %eax
+    movw %ax, -2(%rbx,%rcx,2)
+    ret
+
+native_memory_dealloc:
+    movzwl (%rbx), %ecx
+    movzwl -2(%rbx,%rcx,2), %eax
+    movw $0, -2(%rbx,%rcx,2)
+    testl %eax, %eax
+    jz .Ldealloc_done
+    decl %eax
+    movw heap_free_list(%rip), %dx
+    movw %dx, 2(%rbx,%rax,2)
+    movw %ax, heap_free_list(%rip)
+.Ldealloc_done:
+    ret
+
+vm_run:
+    pushq %rbx
+    leaq ram(%rip), %rbx
+    movq %rsp, vm_saved_rsp(%rip)
+    movw $256, (%rbx)"#;
+
+const EPILOGUE: &str = r#"vm_halt:
+    movq vm_saved_rsp(%rip), %rsp
+    popq %rbx
+    ret
+
+vm_divide_by_zero:
+    leaq divide_by_zero_message(%rip), %rsi
+    movl $divide_by_zero_message_end - divide_by_zero_message, %edx
+    jmp vm_fail
+
+vm_heap_overflow:
+    leaq heap_overflow_message(%rip), %rsi
+    movl $heap_overflow_message_end - heap_overflow_message, %edx
+    jmp vm_fail
+
+vm_fail:
+    movq vm_saved_rsp(%rip), %rsp
+    movl $2, %edi
+    call write@PLT
+    movl $1, %edi
+    call exit@PLT
+
+    .section .note.GNU-stack,"",@progbits"#;
+
+/// Translates a linked VM program into GNU as x86-64 assembly for Linux.
+///
+/// The Hack RAM is an array of 16-bit words addressed through `%rbx`, and the
+/// VM frames are laid out as on the Hack platform, except that return
+/// addresses live on the native stack and the frame keeps a 0 in their slot.
+/// Link the output with `cc prog.s -o prog`. A call to `Sys.halt` or a return
+/// from the entry point ends the program, a division by zero or a full heap
+/// ends it with an error message and exit status 1. When started with `<from> [count]` the
+/// program prints these RAM words before it exits.
+pub(crate) fn write_x86_program(
+    units: &[(String, ASTNode)],
+    bootstrap: Option<&str>,
+) -> Result<String, String> {
+    let mut x86_writer = X86Writer::new(units);
+
+    if let Some(entry_point) = bootstrap {
+        x86_writer.write_call(entry_point, 0)?;
+        x86_writer.emit_code("jmp vm_halt");
+    }
+
+    for (static_prefix, program) in units {
+        x86_writer.static_prefix = static_prefix.to_string();
+        x86_writer.current_scope = static_prefix.to_string();
+        x86_writer.write_program(program)?;
+    }
+    x86_writer.emit_code("jmp vm_halt");
+
+    let mut lines = vec![PRELUDE.to_string()];
+    lines.extend(x86_writer.lines);
+    lines.push(EPILOGUE.to_string());
+    Ok(lines.join("\n") + "\n")
+}
+
+struct X86Writer {
+    static_prefix: String,
+    current_scope: String,
+    function_labels: HashMap<String, String>,
+    label_ids: HashMap<(String, String), usize>,
+    static_addresses: HashMap<(String, u16), u16>,
+    pending_comment: Option<String>,
+    lines: Vec<String>,
+}
+
+impl X86Writer {
+    fn new(units: &[(String, ASTNode)]) -> Self {
+        let mut x86_writer = Self {
+            static_prefix: String::new(),
+            current_scope: String::new(),
+            function_labels: HashMap::new(),
+            label_ids: HashMap::new(),
+            static_addresses: allocate_statics(units),
+            pending_comment: None,
+            lines: vec![],
+        };
+        x86_writer.assign_function_labels(units);
+        x86_writer
+    }
+
+    fn assign_function_labels(&mut self, units: &[(String, ASTNode)]) {
+        for (_, program) in units {
+            let ASTNode::Program { commands } = program else {
+                continue;
+            };
+            for command in commands {
+                if let ASTNode::Function { name, .. } = command {
+                    let label = format!("vm_function_{}", self.function_labels.len());
+                    self.function_labels.insert(name.clone(), label);
+                }
+            }
+        }
+    }
+
+    fn write_program(&mut self, program: &ASTNode) -> Result<(), String> {
+        match program {
+            ASTNode::Program { commands } => {
+                for command in commands {
+                    self.write_command(command)?;
+                }
+                Ok(())
+            }
+            _ => Err("Expected Program node".to_string()),
+        }
+    }
+
+    fn write_command(&mut self, command: &ASTNode) -> Result<(), String> {
+        self.emit_comment(&command.to_command_string());
+
+        match command {
+            ASTNode::Push { segment, index } => self.write_push(segment, *index),
+            ASTNode::Pop { segment, index } => self.write_pop(segment, *index),
+            ASTNode::Add => self.write_binary("addw"),
+            ASTNode::Sub => self.write_binary("subw"),
+            ASTNode::And => self.write_binary("andw"),
+            ASTNode::Or => self.write_binary("orw"),
+            ASTNode::Eq => self.write_comparison("sete"),
+            ASTNode::Gt => self.write_comparison("setg"),
+            ASTNode::Lt => self.write_comparison("setl"),
+            ASTNode::Neg => self.write_unary("negw"),
+            ASTNode::Not => self.write_unary("notw"),
+            ASTNode::Label { name } => {
+                let label = self.label(name);
+                self.emit_label(&label);
+            }
+            ASTNode::Goto { label } => {
+                let label = self.label(label);
+                self.emit_code(&format!("jmp {label}"));
+            }
+            ASTNode::IfGoto { label } => {
+                let label = self.label(label);
+                self.pop_ax();
+                self.emit_code("testw %ax, %ax");
+                self.emit_code(&format!("jnz {label}"));
+            }
+            ASTNode::Function { name, n_locals } => {
+                self.current_scope = name.clone();
+                let label = self.function_labels[name].clone();
+                self.emit_label(&label);
+                for _ in 0..*n_locals {
+                    self.emit_code("movw $0, %ax");
+                    self.push_ax();
+                }
+            }
+            ASTNode::Call { name, n_args } => self.write_call(name, *n_args)?,
+            ASTNode::Return => self.write_return(),
+            ASTNode::Program { .. } => return Err("Unsupported command".to_string()),
+        }
+
+        Ok(())
+    }
+
+    fn write_call(&mut self, callee_name: &str, n_args: u16) -> Result<(), String> {
+        if callee_name == "Sys.halt" {
+            self.emit_code("jmp vm_halt");
+            return Ok(());
+        }
+        if let Some((_, native_label)) = NATIVE_FUNCTIONS.iter().find(|(name, _)| *name == callee_name) {
+            self.emit_code(&format!("call {native_label}"));
+            return Ok(());
+        }
+        let callee_label = self
+            .function_labels
+            .get(callee_name)
+            .cloned()
+            .ok_or_else(|| format!("Call to undefined function {callee_name}"))?;
+
+        // The return address goes to the native stack, its frame slot stays 0
+        self.emit_code("movw $0, %ax");
+        self.push_ax();
+        for offset in [2, 4, 6, 8] {
+            self.emit_code(&format!("movw {offset}(%rbx), %ax"));
+            self.push_ax();
+        }
+        // ARG = SP - n_args - 5, LCL = SP
+        self.emit_code("movw (%rbx), %ax");
+        self.emit_code("movw %ax, 2(%rbx)");
+        self.emit_code(&format!("subw ${}, %ax", n_args as u32 + 5));
+        self.emit_code("movw %ax, 4(%rbx)");
+        self.emit_code(&format!("call {callee_label}"));
+        Ok(())
+    }
+
+    fn write_return(&mut self) {
+        // %r8 = FRAME
+        self.emit_code("movzwl 2(%rbx), %r8d");
+        // *ARG = pop(), SP = ARG + 1
+        self.pop_ax();
+        self.emit_code("movzwl 4(%rbx), %ecx");
+        self.emit_code("movw %ax, (%rbx,%rcx,2)");
+        self.emit_code("incw %cx");
+        self.emit_code("movw %cx, (%rbx)");
+        // THAT, THIS, ARG, LCL = *(FRAME - 1), ..., *(FRAME - 4)
+        for (frame_offset, offset) in [(-2, 8), (-4, 6), (-6, 4), (-8, 2)] {
+            self.emit_code(&format!("movw {frame_offset}(%rbx,%r8,2), %ax"));
+            self.emit_code(&format!("movw %ax, {offset}(%rbx)"));
+        }
+        self.emit_code("ret");
+    }
+
+    fn write_push(&mut self, segment: &Segment, index: u16) {
+        match segment {
+            Segment::Constant => self.emit_code(&format!("movw ${index}, %ax")),
+            _ => {
+                let operand = self.segment_operand(segment, index);
+                self.emit_code(&format!("movw {operand}, %ax"));
+            }
+        }
+        self.push_ax();
+    }
+
+    fn write_pop(&mut self, segment: &Segment, index: u16) {
+        self.pop_ax();
+        let operand = self.segment_operand(segment, index);
+        self.emit_code(&format!("movw %ax, {operand}"));
+    }
+
+    fn write_binary(&mut self, instruction: &str) {
+        self.emit_code("decw (%rbx)");
+        self.emit_code("movzwl (%rbx), %ecx");
+        self.emit_code("movw (%rbx,%rcx,2), %dx");
+        self.emit_code(&format!("{instruction} %dx, -2(%rbx,%rcx,2)"));
+    }
+
+    fn write_comparison(&mut self, set_instruction: &str) {
+        self.emit_code("decw (%rbx)");
+        self.emit_code("movzwl (%rbx), %ecx");
+        self.emit_code("movw (%rbx,%rcx,2), %dx");
+        self.emit_code("cmpw %dx, -2(%rbx,%rcx,2)");
+        self.emit_code(&format!("{set_instruction} %al"));
+        self.emit_code("movzbw %al, %ax");
+        self.emit_code("negw %ax");
+        self.emit_code("movw %ax, -2(%rbx,%rcx,2)");
+    }
+
+    fn write_unary(&mut self, instruction: &str) {
+        self.emit_code("movzwl (%rbx), %ecx");
+        self.emit_code(&format!("{instruction} -2(%rbx,%rcx,2)"));
+    }
+
+    /// Memory operand of a segment entry. May clobber `%ecx`, so `%ax` is free
+    /// to carry the value to push or pop.
+    fn segment_operand(&mut self, segment: &Segment, index: u16) -> String {
+        let base_offset = match segment {
+            Segment::Static => {
+                let address = self.static_addresses[&(self.static_prefix.clone(), index)];
+                return format!("{}(%rbx)", 2 * address as u32);
+            }
+            Segment::Temp => return format!("{}(%rbx)", 2 * (5 + index as u32)),
+            Segment::Pointer => return format!("{}(%rbx)", 2 * (3 + index as u32)),
+            Segment::Constant => unreachable!("constants have no memory operand"),
+            Segment::Local => 2,
+            Segment::Argument => 4,
+            Segment::This => 6,
+            Segment::That => 8,
+        };
+        self.emit_code(&format!("movw {base_offset}(%rbx), %cx"));
+        if index > 0 {
+            self.emit_code(&format!("addw ${index}, %cx"));
+        }
+        self.emit_code("movzwl %cx, %ecx");
+        "(%rbx,%rcx,2)".to_string()
+    }
+
+    fn push_ax(&mut self) {
+        self.emit_code("movzwl (%rbx), %ecx");
+        self.emit_code("movw %ax, (%rbx,%rcx,2)");
+        self.emit_code("incw (%rbx)");
+    }
+
+    fn pop_ax(&mut self) {
+        self.emit_code("decw (%rbx)");
+        self.emit_code("movzwl (%rbx), %ecx");
+        self.emit_code("movw (%rbx,%rcx,2), %ax");
+    }
+
+    fn label(&mut self, name: &str) -> String {
+        let key = (self.current_scope.clone(), name.to_string());
+        let next_id = self.label_ids.len();
+        let id = *self.label_ids.entry(key).or_insert(next_id);
+        format!(".L{id}")
+    }
+
+    fn emit_comment(&mut self, comment: &str) {
+        self.pending_comment = Some(comment.to_string());
+    }
+
+    fn emit_label(&mut self, label: &str) {
+        self.emit_line(&format!("{label}:"));
+    }
+
+    fn emit_code(&mut self, code: &str) {
+        self.emit_line(&format!("    {code}"));
+    }
+
+    fn emit_line(&mut self, line: &str) {
+        if let Some(comment) = self.pending_comment.take() {
+            self.lines.push(format!("{line} # {comment}"));
+        } else {
+            self.lines.push(line.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vmtrans::native_tests::{compile_and_run, SUM_PROGRAM};
+    use crate::vmtrans::parser::parse_vm_code;
+
+    #[test]
+    fn test_write_x86_program() {
+        let code = "function Main.main 0\npush constant 7\npush local 2\ncall Math.multiply 2\npop static 3\nreturn\n";
+        let units = vec![("Main".to_string(), parse_vm_code(code).unwrap())];
+
+        let asm = write_x86_program(&units, Some("Main.main")).unwrap();
+
+        assert!(asm.contains("    call vm_function_0\n    jmp vm_halt\nvm_function_0: # function Main.main 0\n"));
+        assert!(asm.contains("    movw $7, %ax # push constant 7\n"));
+        assert!(asm.contains("    movw 2(%rbx), %cx # push local 2\n    addw $2, %cx\n"));
+        assert!(asm.contains("    call native_math_multiply # call Math.multiply 2\n"));
+        assert!(asm.contains("    movw %ax, 32(%rbx)\n"));
+    }
+
+    /// Assembles and runs a program, `None` where it cannot run natively.
+    fn run_x86_program(name: &str, code: &str, args: &[&str]) -> Option<std::process::Output> {
+        if cfg!(not(all(target_arch = "x86_64", target_os = "linux"))) {
+            return None;
+        }
+        let units = vec![("Main".to_string(), parse_vm_code(code).unwrap())];
+        let asm = write_x86_program(&units, Some("Main.main")).unwrap();
+        compile_and_run(&format!("x86-{name}"), "prog.s", &asm, args)
+    }
+
+    #[test]
+    fn test_run_x86_program() {
+        let Some(output) = run_x86_program("program", SUM_PROGRAM, &["16", "2"]) else {
+            return;
+        };
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "RAM[16] = 55\nRAM[17] = -1\n");
+    }
+
+    #[test]
+    fn test_run_x86_native_math() {
+        let code = "function Main.main 0\npush constant 7\npush constant 300\ncall Math.multiply 2\nneg\npop static 0\npush constant 81\ncall Math.sqrt 1\npop static 1\npush constant 0\nreturn\n";
+        let Some(output) = run_x86_program("math", code, &["16", "2"]) else {
+            return;
+        };
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "RAM[16] = -2100\nRAM[17] = 9\n");
+    }
+
+    #[test]
+    fn test_run_x86_divide_by_zero() {
+        let code = "function Main.main 0\npush constant 7\npush constant 0\ncall Math.divide 2\npop static 0\npush constant 0\nreturn\n";
+        let Some(output) = run_x86_program("divide", code, &[]) else {
+            return;
+        };
+        assert_eq!(output.status.code(), Some(1));
+        assert_eq!(String::from_utf8_lossy(&output.stderr), "Math.divide: division by zero\n");
+    }
+
+    #[test]
+    fn test_run_x86_native_memory() {
+        // The freed block of a is reused for c
+        let code = "function Main.main 0\npush constant 3\ncall Memory.alloc 1\npop static 0\npush constant 5\ncall Memory.alloc 1\npop static 1\npush static 0\ncall Memory.deAlloc 1\npop temp 0\npush constant 2\ncall Memory.alloc 1\npop static 2\npush constant 0\nreturn\n";
+        let Some(output) = run_x86_program("memory", code, &["16", "3"]) else {
+            return;
+        };
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "RAM[16] = 16381\nRAM[17] = 16375\nRAM[18] = 16381\n");
+
+        let code = "function Main.main 0\npush constant 20000\ncall Memory.alloc 1\npop static 0\npush constant 0\nreturn\n";
+        let Some(output) = run_x86_program("heap", code, &[]) else {
+            return;
+        };
+        assert_eq!(output.status.code(), Some(1));
+        assert_eq!(String::from_utf8_lossy(&output.stderr), "Memory.alloc: heap overflow\n");
+    }
+}
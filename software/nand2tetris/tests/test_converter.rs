@@ -16,7 +16,7 @@ fn parse_jack_file(file_path: &str) {
     let mut parser = Parser::new(&mut lexer);
 
     let parse_tree = parser.parse_class();
-    assert!(parse_tree.is_ok(), "{}", parse_tree.err().unwrap());
+    assert!(parse_tree.is_ok(), "{:?}", parse_tree.err().unwrap());
 
     let ast = nand2tetris::jack::parse_tree_converter::convert_class(&parse_tree.unwrap());
     assert!(ast.is_ok(), "{}", ast.err().unwrap());
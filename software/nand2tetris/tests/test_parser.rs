@@ -17,5 +17,5 @@ fn parse_jack_file(file_path: &str) {
 
     let ast = parser.parse_class();
 
-    assert!(ast.is_ok(), "{}", ast.err().unwrap());
+    assert!(ast.is_ok(), "{:?}", ast.err().unwrap());
 }